@@ -0,0 +1,14 @@
+-- Access token denylist, keyed by the token's jti claim.
+-- Rows are only needed until the token would have expired anyway.
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+    jti TEXT PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_revoked_tokens_expires_at
+  ON revoked_tokens(expires_at);
+
+CREATE INDEX IF NOT EXISTS idx_revoked_tokens_revoked_at
+  ON revoked_tokens(revoked_at);
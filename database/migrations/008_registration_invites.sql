@@ -0,0 +1,11 @@
+-- Single-use invite codes for invite-only self registration (stored as SHA-256 hashes)
+CREATE TABLE IF NOT EXISTS registration_invites (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    code_hash TEXT UNIQUE NOT NULL,
+    email VARCHAR(255),
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    used_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
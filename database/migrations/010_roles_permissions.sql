@@ -0,0 +1,69 @@
+-- Role-based access control. user_roles is the source of truth for the Rust API;
+-- users.is_admin is kept in sync with the 'admin' role for the other backends.
+CREATE TABLE IF NOT EXISTS roles (
+    name TEXT PRIMARY KEY,
+    description TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS permissions (
+    name TEXT PRIMARY KEY,
+    description TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS role_permissions (
+    role TEXT NOT NULL REFERENCES roles(name) ON DELETE CASCADE,
+    permission TEXT NOT NULL REFERENCES permissions(name) ON DELETE CASCADE,
+    PRIMARY KEY (role, permission)
+);
+
+CREATE TABLE IF NOT EXISTS user_roles (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role TEXT NOT NULL REFERENCES roles(name) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, role)
+);
+
+INSERT INTO permissions (name, description) VALUES
+    ('users:read', 'List and view any user'),
+    ('users:write', 'Create, update and delete users and assign roles'),
+    ('posts:delete_any', 'Delete posts written by other users'),
+    ('invites:create', 'Create registration invites')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO roles (name, description) VALUES
+    ('admin', 'Full access'),
+    ('moderator', 'Moderates content, cannot manage users')
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role, permission) VALUES
+    ('admin', 'users:read'),
+    ('admin', 'users:write'),
+    ('admin', 'posts:delete_any'),
+    ('admin', 'invites:create'),
+    ('moderator', 'posts:delete_any')
+ON CONFLICT DO NOTHING;
+
+-- Backfill existing admins
+INSERT INTO user_roles (user_id, role)
+SELECT id, 'admin' FROM users WHERE is_admin
+ON CONFLICT DO NOTHING;
+
+CREATE OR REPLACE FUNCTION sync_is_admin() RETURNS trigger AS $$
+DECLARE
+  target UUID;
+BEGIN
+  IF TG_OP = 'DELETE' THEN
+    target := OLD.user_id;
+  ELSE
+    target := NEW.user_id;
+  END IF;
+
+  UPDATE users
+  SET is_admin = EXISTS (SELECT 1 FROM user_roles WHERE user_id = target AND role = 'admin')
+  WHERE id = target;
+  RETURN NULL;
+END $$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS user_roles_sync_is_admin ON user_roles;
+CREATE TRIGGER user_roles_sync_is_admin AFTER INSERT OR DELETE ON user_roles
+  FOR EACH ROW EXECUTE FUNCTION sync_is_admin();
@@ -0,0 +1,8 @@
+-- Soft delete: deleted users are hidden and can be restored until the grace period ends.
+-- The purge then anonymises the row instead of deleting it, so their posts and comments stay.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS purged_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_users_pending_purge
+  ON users(deleted_at)
+  WHERE deleted_at IS NOT NULL AND purged_at IS NULL;
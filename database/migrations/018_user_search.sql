@@ -0,0 +1,9 @@
+-- Trigram indexes for GET /users?q=: case-insensitive partial matches on username and email
+-- (ILIKE '%...%') can use them, and similarity() ranks the results.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS idx_users_username_trgm
+  ON users USING gin (username gin_trgm_ops);
+
+CREATE INDEX IF NOT EXISTS idx_users_email_trgm
+  ON users USING gin (email gin_trgm_ops);
@@ -0,0 +1,46 @@
+-- Follow graph: follower_id follows followee_id.
+CREATE TABLE IF NOT EXISTS follows (
+    follower_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    followee_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (follower_id, followee_id),
+    CHECK (follower_id <> followee_id)
+);
+
+-- Followers and following lists, newest first
+CREATE INDEX IF NOT EXISTS idx_follows_followee_created_at
+  ON follows(followee_id, created_at DESC);
+
+CREATE INDEX IF NOT EXISTS idx_follows_follower_created_at
+  ON follows(follower_id, created_at DESC);
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS followers_count integer NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS following_count integer NOT NULL DEFAULT 0;
+
+-- Both counts are updated by one statement, which locks the two rows in the same order
+-- whoever follows whom: A and B following each other at once cannot deadlock.
+CREATE OR REPLACE FUNCTION increment_follow_counts() RETURNS trigger AS $$
+BEGIN
+  UPDATE users
+  SET followers_count = followers_count + CASE WHEN id = NEW.followee_id THEN 1 ELSE 0 END,
+      following_count = following_count + CASE WHEN id = NEW.follower_id THEN 1 ELSE 0 END
+  WHERE id IN (NEW.follower_id, NEW.followee_id);
+  RETURN NEW;
+END $$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION decrement_follow_counts() RETURNS trigger AS $$
+BEGIN
+  UPDATE users
+  SET followers_count = followers_count - CASE WHEN id = OLD.followee_id THEN 1 ELSE 0 END,
+      following_count = following_count - CASE WHEN id = OLD.follower_id THEN 1 ELSE 0 END
+  WHERE id IN (OLD.follower_id, OLD.followee_id);
+  RETURN OLD;
+END $$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS follows_inc ON follows;
+CREATE TRIGGER follows_inc AFTER INSERT ON follows
+  FOR EACH ROW EXECUTE FUNCTION increment_follow_counts();
+
+DROP TRIGGER IF EXISTS follows_dec ON follows;
+CREATE TRIGGER follows_dec AFTER DELETE ON follows
+  FOR EACH ROW EXECUTE FUNCTION decrement_follow_counts();
@@ -0,0 +1,16 @@
+-- A key never grants more than its owner currently holds
+SELECT k.id,
+       k.user_id,
+       k.expires_at,
+       ARRAY(
+           SELECT DISTINCT rp.permission
+           FROM user_roles ur
+           JOIN role_permissions rp ON rp.role = ur.role
+           WHERE ur.user_id = k.user_id AND rp.permission = ANY(k.scopes)
+           ORDER BY rp.permission
+       ) AS permissions
+FROM api_keys k
+JOIN users u ON u.id = k.user_id AND u.deleted_at IS NULL
+WHERE k.key_hash = $1
+  AND k.revoked_at IS NULL
+  AND (k.expires_at IS NULL OR k.expires_at > NOW());
@@ -0,0 +1,3 @@
+INSERT INTO api_keys (user_id, name, key_prefix, key_hash, scopes, expires_at)
+VALUES ($1, $2, $3, $4, $5, $6)
+RETURNING id, name, key_prefix, scopes, expires_at, revoked_at, created_at;
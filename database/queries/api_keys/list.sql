@@ -0,0 +1,4 @@
+SELECT id, name, key_prefix, scopes, expires_at, revoked_at, created_at
+FROM api_keys
+WHERE user_id = $1
+ORDER BY created_at DESC;
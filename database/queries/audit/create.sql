@@ -0,0 +1,2 @@
+INSERT INTO audit_log (actor_id, user_id, method, path, status)
+VALUES ($1, $2, $3, $4, $5);
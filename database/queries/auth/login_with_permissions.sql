@@ -0,0 +1,15 @@
+SELECT u.id,
+       u.password_hash,
+       ARRAY(
+           SELECT DISTINCT rp.permission
+           FROM user_roles ur
+           JOIN role_permissions rp ON rp.role = ur.role
+           WHERE ur.user_id = u.id
+           ORDER BY rp.permission
+       ) AS permissions,
+       EXISTS(
+           SELECT 1 FROM user_totp t
+           WHERE t.user_id = u.id AND t.confirmed_at IS NOT NULL
+       ) AS mfa_enabled
+FROM users u
+WHERE u.email = $1 AND u.deleted_at IS NULL;
@@ -0,0 +1,4 @@
+SELECT id, author_id, post_id, content, created_at
+FROM comments
+WHERE author_id = $1
+ORDER BY created_at ASC;
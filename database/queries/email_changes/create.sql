@@ -0,0 +1,7 @@
+-- A new request replaces the user's previous one
+WITH superseded AS (
+    DELETE FROM email_change_tokens
+    WHERE user_id = $1 OR expires_at <= NOW()
+)
+INSERT INTO email_change_tokens (user_id, new_email, token_hash, expires_at)
+VALUES ($1, $2, $3, $4);
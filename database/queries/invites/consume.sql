@@ -0,0 +1,7 @@
+UPDATE registration_invites
+SET used_at = NOW(), used_by = $3
+WHERE code_hash = $1
+  AND used_at IS NULL
+  AND expires_at > NOW()
+  AND (email IS NULL OR LOWER(email) = LOWER($2))
+RETURNING id;
@@ -0,0 +1,2 @@
+INSERT INTO registration_invites (code_hash, email, created_by, expires_at)
+VALUES ($1, $2, $3, $4);
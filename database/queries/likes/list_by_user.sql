@@ -0,0 +1,4 @@
+SELECT post_id, created_at
+FROM post_likes
+WHERE user_id = $1
+ORDER BY created_at ASC;
@@ -0,0 +1 @@
+DELETE FROM mfa_challenges WHERE id = $1;
@@ -0,0 +1,6 @@
+-- Expired challenges are cleaned up whenever a new one is created
+WITH expired AS (
+    DELETE FROM mfa_challenges WHERE expires_at < NOW()
+)
+INSERT INTO mfa_challenges (user_id, token_hash, cookie_mode, expires_at)
+VALUES ($1, $2, $3, $4);
@@ -0,0 +1,2 @@
+INSERT INTO user_recovery_codes (user_id, code_hash)
+SELECT $1, code_hash FROM UNNEST($2::text[]) AS code_hash;
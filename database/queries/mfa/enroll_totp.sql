@@ -0,0 +1,7 @@
+-- Restarting an unconfirmed enrollment replaces its secret, a confirmed one is left alone
+INSERT INTO user_totp (user_id, secret)
+VALUES ($1, $2)
+ON CONFLICT (user_id) DO UPDATE
+SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = NOW()
+WHERE user_totp.confirmed_at IS NULL
+RETURNING user_id;
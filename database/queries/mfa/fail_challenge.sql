@@ -0,0 +1 @@
+UPDATE mfa_challenges SET attempts = attempts + 1 WHERE id = $1;
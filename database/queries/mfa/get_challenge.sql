@@ -0,0 +1,3 @@
+SELECT id, user_id, cookie_mode
+FROM mfa_challenges
+WHERE token_hash = $1 AND expires_at > NOW() AND attempts < $2;
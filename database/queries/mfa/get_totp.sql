@@ -0,0 +1,3 @@
+SELECT secret, confirmed_at
+FROM user_totp
+WHERE user_id = $1;
@@ -0,0 +1,4 @@
+-- Only succeeds for a time step newer than the last accepted one
+UPDATE user_totp
+SET last_used_step = $2
+WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2);
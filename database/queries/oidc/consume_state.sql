@@ -0,0 +1,3 @@
+DELETE FROM oidc_login_states
+WHERE state_hash = $1 AND expires_at > NOW()
+RETURNING code_verifier, nonce, cookie_mode;
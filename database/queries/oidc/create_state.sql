@@ -0,0 +1,6 @@
+-- Abandoned login attempts are cleaned up whenever a new one starts
+WITH expired AS (
+    DELETE FROM oidc_login_states WHERE expires_at < NOW()
+)
+INSERT INTO oidc_login_states (state_hash, code_verifier, nonce, cookie_mode, expires_at)
+VALUES ($1, $2, $3, $4, $5);
@@ -0,0 +1,4 @@
+SELECT i.user_id
+FROM user_identities i
+JOIN users u ON u.id = i.user_id AND u.deleted_at IS NULL
+WHERE i.issuer = $1 AND i.subject = $2;
@@ -0,0 +1,2 @@
+INSERT INTO outbox_messages (kind, recipient, subject, body)
+VALUES ($1, $2, $3, $4);
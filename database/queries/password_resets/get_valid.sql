@@ -0,0 +1,3 @@
+SELECT user_id
+FROM password_reset_tokens
+WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW();
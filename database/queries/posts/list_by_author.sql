@@ -0,0 +1,8 @@
+SELECT p.id,
+       p.author_id,
+       p.content,
+       p.created_at,
+       p.likes_count::bigint AS like_count
+FROM posts p
+WHERE p.author_id = $1
+ORDER BY p.created_at ASC;
@@ -0,0 +1,15 @@
+SELECT rt.id,
+       rt.user_id,
+       rt.family_id,
+       rt.expires_at,
+       rt.revoked_at,
+       ARRAY(
+           SELECT DISTINCT rp.permission
+           FROM user_roles ur
+           JOIN role_permissions rp ON rp.role = ur.role
+           WHERE ur.user_id = rt.user_id
+           ORDER BY rp.permission
+       ) AS permissions
+FROM refresh_tokens rt
+JOIN users u ON u.id = rt.user_id AND u.deleted_at IS NULL
+WHERE rt.token_hash = $1;
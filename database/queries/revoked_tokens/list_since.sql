@@ -0,0 +1,3 @@
+SELECT jti, expires_at, revoked_at
+FROM revoked_tokens
+WHERE revoked_at > $1 AND expires_at > NOW();
@@ -0,0 +1,4 @@
+INSERT INTO user_roles (user_id, role)
+SELECT $1, role FROM UNNEST($2::text[]) AS role
+ON CONFLICT DO NOTHING
+RETURNING role;
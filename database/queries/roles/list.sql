@@ -0,0 +1,10 @@
+SELECT r.name,
+       r.description,
+       ARRAY(
+           SELECT rp.permission
+           FROM role_permissions rp
+           WHERE rp.role = r.name
+           ORDER BY rp.permission
+       ) AS permissions
+FROM roles r
+ORDER BY r.name;
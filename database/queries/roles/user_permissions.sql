@@ -0,0 +1,7 @@
+SELECT ARRAY(
+    SELECT DISTINCT rp.permission
+    FROM user_roles ur
+    JOIN role_permissions rp ON rp.role = ur.role
+    WHERE ur.user_id = $1
+    ORDER BY rp.permission
+);
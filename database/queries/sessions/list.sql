@@ -0,0 +1,4 @@
+SELECT id, user_agent, ip_address, created_at, last_seen_at
+FROM sessions
+WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+ORDER BY last_seen_at DESC;
@@ -0,0 +1,4 @@
+UPDATE sessions
+SET revoked_at = NOW()
+WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+RETURNING id;
@@ -0,0 +1,3 @@
+UPDATE sessions
+SET last_seen_at = NOW(), ip_address = $2, expires_at = $3
+WHERE id = $1 AND revoked_at IS NULL;
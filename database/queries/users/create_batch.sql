@@ -0,0 +1,4 @@
+INSERT INTO users (username, email, password_hash, bio)
+SELECT * FROM UNNEST($1::varchar[], $2::varchar[], $3::text[], $4::text[])
+ON CONFLICT DO NOTHING
+RETURNING id, email;
@@ -0,0 +1,3 @@
+SELECT id, username, email, bio, created_at
+FROM users
+WHERE id = $1 AND deleted_at IS NULL;
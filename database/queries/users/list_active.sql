@@ -0,0 +1,5 @@
+SELECT id, username, email, bio, created_at
+FROM users
+WHERE deleted_at IS NULL
+ORDER BY created_at DESC
+LIMIT $1 OFFSET $2;
@@ -0,0 +1,17 @@
+SELECT u.id,
+       u.username,
+       u.bio,
+       u.created_at,
+       p.posts_count,
+       (SELECT COUNT(*) FROM comments c WHERE c.author_id = u.id) AS comments_count,
+       p.likes_received,
+       u.followers_count::BIGINT AS followers_count,
+       u.following_count::BIGINT AS following_count
+FROM users u
+CROSS JOIN LATERAL (
+    SELECT COUNT(*) AS posts_count,
+           COALESCE(SUM(likes_count), 0)::BIGINT AS likes_received
+    FROM posts
+    WHERE author_id = u.id
+) p
+WHERE u.username = $1 AND u.deleted_at IS NULL;
@@ -0,0 +1,57 @@
+-- Anonymises users deleted more than $1 days ago, at most $2 per call, and drops their
+-- credentials and personal data, including outbox messages and invites sent to their address.
+-- The row stays so their posts, comments and likes keep an author.
+WITH batch AS (
+    SELECT id, email
+    FROM users
+    WHERE deleted_at <= NOW() - make_interval(days => $1::int) AND purged_at IS NULL
+    ORDER BY deleted_at
+    LIMIT $2
+    FOR UPDATE SKIP LOCKED
+), purged AS (
+    UPDATE users u
+    SET username = 'deleted-' || u.id,
+        email = 'deleted-' || u.id || '@deleted.invalid',
+        password_hash = '!',
+        bio = NULL,
+        purged_at = NOW()
+    FROM batch
+    WHERE u.id = batch.id
+    RETURNING u.id
+), roles AS (
+    DELETE FROM user_roles WHERE user_id IN (SELECT id FROM purged)
+), keys AS (
+    DELETE FROM api_keys WHERE user_id IN (SELECT id FROM purged)
+), user_sessions AS (
+    DELETE FROM sessions WHERE user_id IN (SELECT id FROM purged)
+), refresh AS (
+    DELETE FROM refresh_tokens WHERE user_id IN (SELECT id FROM purged)
+), totp AS (
+    DELETE FROM user_totp WHERE user_id IN (SELECT id FROM purged)
+), recovery AS (
+    DELETE FROM user_recovery_codes WHERE user_id IN (SELECT id FROM purged)
+), challenges AS (
+    DELETE FROM mfa_challenges WHERE user_id IN (SELECT id FROM purged)
+), identities AS (
+    DELETE FROM user_identities WHERE user_id IN (SELECT id FROM purged)
+), resets AS (
+    DELETE FROM password_reset_tokens WHERE user_id IN (SELECT id FROM purged)
+), email_changes AS (
+    DELETE FROM email_change_tokens WHERE user_id IN (SELECT id FROM purged)
+), outbox AS (
+    -- Messages to the address, or to an email change still pending
+    DELETE FROM outbox_messages
+    WHERE LOWER(recipient) IN (
+        SELECT LOWER(email) FROM batch
+        UNION
+        SELECT LOWER(new_email) FROM email_change_tokens WHERE user_id IN (SELECT id FROM purged)
+    )
+), invites AS (
+    UPDATE registration_invites
+    SET email = NULL
+    WHERE used_by IN (SELECT id FROM purged) OR LOWER(email) IN (SELECT LOWER(email) FROM batch)
+), follows AS (
+    DELETE FROM follows
+    WHERE follower_id IN (SELECT id FROM purged) OR followee_id IN (SELECT id FROM purged)
+)
+SELECT COUNT(*) FROM purged;
@@ -0,0 +1,4 @@
+-- Only replaces the hash that was verified, so a concurrent password change wins
+UPDATE users
+SET password_hash = $3
+WHERE id = $1 AND password_hash = $2;
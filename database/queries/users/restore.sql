@@ -0,0 +1,6 @@
+UPDATE users
+SET deleted_at = NULL
+WHERE id = $1
+  AND purged_at IS NULL
+  AND deleted_at > NOW() - make_interval(days => $2::int)
+RETURNING id, username, email, bio, created_at;
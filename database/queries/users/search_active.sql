@@ -0,0 +1,10 @@
+SELECT id, username, email, bio, created_at
+FROM users
+WHERE deleted_at IS NULL
+  AND ($1::text IS NULL OR username ILIKE $2 OR email ILIKE $2)
+  AND ($3::boolean IS NULL OR is_admin = $3)
+  AND ($4::timestamptz IS NULL OR created_at >= $4)
+  AND ($5::timestamptz IS NULL OR created_at < $5)
+ORDER BY GREATEST(similarity(username, $1), similarity(email, $1)) DESC NULLS LAST,
+         created_at DESC
+LIMIT $6 OFFSET $7;
@@ -0,0 +1,4 @@
+UPDATE users
+SET bio = $2
+WHERE id = $1 AND deleted_at IS NULL
+RETURNING id, username, email, bio, created_at;
@@ -0,0 +1,3 @@
+UPDATE users
+SET bio = $2
+WHERE id = $1;
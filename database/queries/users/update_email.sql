@@ -0,0 +1,3 @@
+UPDATE users
+SET email = $2
+WHERE id = $1;
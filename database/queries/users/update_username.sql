@@ -0,0 +1,3 @@
+UPDATE users
+SET username = $2
+WHERE id = $1;
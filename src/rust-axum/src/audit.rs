@@ -0,0 +1,31 @@
+use axum::http::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::sql::SQL_CREATE_AUDIT_ENTRY;
+
+/// A request made by an admin on behalf of another user.
+#[derive(Debug)]
+pub struct AuditEntry {
+    pub actor_id: Uuid,
+    pub user_id: Uuid,
+    pub method: String,
+    pub path: String,
+}
+
+/// Written once the response is known. A failed write is logged, the response is still sent:
+/// the request has already been handled at that point.
+pub async fn record(db: &PgPool, entry: AuditEntry, status: StatusCode) {
+    let result = sqlx::query(SQL_CREATE_AUDIT_ENTRY)
+        .bind(entry.actor_id)
+        .bind(entry.user_id)
+        .bind(&entry.method)
+        .bind(&entry.path)
+        .bind(status.as_u16() as i16)
+        .execute(db)
+        .await;
+
+    if let Err(e) = result {
+        tracing::error!("Failed to write audit entry {:?}: {:?}", entry, e);
+    }
+}
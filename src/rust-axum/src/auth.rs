@@ -1,62 +1,602 @@
 use axum::{
-    extract::{Request, State},
-    http::HeaderMap,
+    extract::{FromRequestParts, Request, State},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, Method},
     middleware::Next,
     response::Response,
 };
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use axum_extra::extract::cookie::CookieJar;
+use anyhow::{bail, Context};
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use simple_asn1::ASN1Block;
+use sqlx::PgPool;
 use uuid::Uuid;
-use std::env;
+use std::{
+    collections::HashMap,
+    env,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::error::AppError;
+use crate::{
+    audit::{self, AuditEntry},
+    error::AppError,
+    hash_pool::HashPool,
+    models::ApiKeyAuthRow,
+    session::{verify_csrf, CookieConfig, SESSION_COOKIE},
+    sql::{SQL_AUTHENTICATE_API_KEY, SQL_IS_USER_ACTIVE},
+    AppState,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // user id
     pub exp: usize,  // expiration time
-    pub is_admin: bool,
+    pub jti: String, // token id, used for revocation
+    /// Space-separated permissions granted through the user's roles
+    pub scope: String,
+    /// Set when the request authenticated with a personal access token instead of a JWT
+    #[serde(skip)]
+    pub api_key_id: Option<Uuid>,
+    /// Login session the token was issued for, revoking the session revokes the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>,
+    /// Admin acting as `sub` through an impersonation token (RFC 8693 `act` claim)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: String,
+}
+
+impl Claims {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.has_scope(permission.as_str())
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope.split_whitespace().any(|granted| granted == scope)
+    }
+}
+
+/// Permissions checked by the API. Their names match the `permissions` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    UsersRead,
+    UsersWrite,
+    PostsDeleteAny,
+    InvitesCreate,
+    UsersImpersonate,
+}
+
+impl Permission {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UsersRead => "users:read",
+            Self::UsersWrite => "users:write",
+            Self::PostsDeleteAny => "posts:delete_any",
+            Self::InvitesCreate => "invites:create",
+            Self::UsersImpersonate => "users:impersonate",
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct AuthConfig {
-    pub jwt_secret: String,
+    pub jwt_keys: Arc<JwtKeys>,
     pub jwt_expire_minutes: i64,
+    pub refresh_token_expire_days: i64,
+    pub registration_mode: RegistrationMode,
+    pub invite_expire_hours: i64,
+    pub password_reset_expire_minutes: i64,
+    pub email_change_expire_hours: i64,
+    /// Deleted users can be restored for this long, then they are anonymised
+    pub user_delete_grace_days: i64,
+    pub impersonation_expire_minutes: i64,
+    pub password_policy: PasswordHashPolicy,
+    pub cookies: CookieConfig,
+    /// Issuer shown by authenticator apps
+    pub totp_issuer: String,
+    /// Verified against when the email is unknown, so timing does not reveal which accounts exist
+    pub dummy_password_hash: String,
 }
 
-impl Default for AuthConfig {
-    fn default() -> Self {
-        Self {
-            jwt_secret: env::var("JWT_SECRET").unwrap_or_else(|_| "dev-secret".to_string()),
-            jwt_expire_minutes: env::var("JWT_EXPIRE_MINUTES")
-                .unwrap_or_else(|_| "60".to_string())
+/// Who may use `POST /auth/register`, set with `REGISTRATION_MODE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationMode {
+    Open,
+    InviteOnly,
+    Disabled,
+}
+
+impl std::str::FromStr for RegistrationMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(Self::Open),
+            "invite" => Ok(Self::InviteOnly),
+            "disabled" => Ok(Self::Disabled),
+            other => bail!("Unsupported REGISTRATION_MODE: {} (expected open, invite or disabled)", other),
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let password_policy = PasswordHashPolicy::from_env()?;
+        let totp_issuer = env::var("TOTP_ISSUER").unwrap_or_else(|_| "apibench".to_string());
+        if totp_issuer.contains(':') {
+            bail!("TOTP_ISSUER must not contain ':'");
+        }
+        let jwt_expire_minutes = env::var("JWT_EXPIRE_MINUTES")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse()
+            .unwrap_or(60);
+
+        Ok(Self {
+            jwt_keys: Arc::new(JwtKeys::from_env(jwt_expire_minutes)?),
+            jwt_expire_minutes,
+            refresh_token_expire_days: env::var("REFRESH_TOKEN_EXPIRE_DAYS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            registration_mode: env::var("REGISTRATION_MODE")
+                .as_deref()
+                .unwrap_or("disabled")
+                .parse()?,
+            invite_expire_hours: env::var("INVITE_EXPIRE_HOURS")
+                .unwrap_or_else(|_| "72".to_string())
+                .parse()
+                .unwrap_or(72),
+            password_reset_expire_minutes: env::var("PASSWORD_RESET_EXPIRE_MINUTES")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            email_change_expire_hours: env::var("EMAIL_CHANGE_EXPIRE_HOURS")
+                .unwrap_or_else(|_| "24".to_string())
+                .parse()
+                .unwrap_or(24),
+            user_delete_grace_days: env::var("USER_DELETE_GRACE_DAYS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .unwrap_or(30),
+            impersonation_expire_minutes: env::var("IMPERSONATION_EXPIRE_MINUTES")
+                .unwrap_or_else(|_| "15".to_string())
                 .parse()
-                .unwrap_or(60),
+                .unwrap_or(15),
+            cookies: CookieConfig::from_env()?,
+            totp_issuer,
+            dummy_password_hash: password_policy.hash(&generate_opaque_token())?,
+            password_policy,
+        })
+    }
+}
+
+/// Key ring used to sign and verify access tokens.
+///
+/// New tokens are signed with the active key and carry its `kid` (`JWT_KEY_ID`).
+/// Keys listed in `JWT_RETIRED_KEYS` are only used for verification, until
+/// `JWT_KEY_GRACE_MINUTES` after their retirement time.
+///
+/// HS256 uses the shared `JWT_SECRET`. RS256 and EdDSA load PEM keys from
+/// `JWT_PRIVATE_KEY_PATH` / `JWT_PUBLIC_KEY_PATH` and publish the public keys
+/// so other services can verify tokens without the private key.
+pub struct JwtKeys {
+    pub algorithm: Algorithm,
+    pub signing_kid: String,
+    pub encoding_key: EncodingKey,
+    verification_keys: HashMap<String, VerificationKey>,
+}
+
+struct VerificationKey {
+    decoding_key: DecodingKey,
+    /// Public JWK, never set for HS256 secrets
+    jwk: Option<Jwk>,
+    /// `None` for the active key, end of the grace period for retired keys
+    valid_until: Option<DateTime<Utc>>,
+}
+
+impl JwtKeys {
+    fn from_env(jwt_expire_minutes: i64) -> anyhow::Result<Self> {
+        let algorithm = match env::var("JWT_ALGORITHM").as_deref().unwrap_or("HS256") {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            "EdDSA" => Algorithm::EdDSA,
+            other => bail!("Unsupported JWT_ALGORITHM: {} (expected HS256, RS256 or EdDSA)", other),
+        };
+        let signing_kid = env::var("JWT_KEY_ID").unwrap_or_else(|_| "default".to_string());
+        // Retired keys must at least outlive the tokens they signed
+        let grace_period = chrono::Duration::minutes(
+            env::var("JWT_KEY_GRACE_MINUTES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(jwt_expire_minutes),
+        );
+
+        let (encoding_key, active_key) = match algorithm {
+            Algorithm::HS256 => {
+                let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "dev-secret".to_string());
+                (
+                    EncodingKey::from_secret(secret.as_ref()),
+                    VerificationKey::load(algorithm, &signing_kid, secret.as_bytes(), None)?,
+                )
+            }
+            _ => {
+                let private_pem = read_pem_file("JWT_PRIVATE_KEY_PATH")?;
+                let public_pem = read_pem_file("JWT_PUBLIC_KEY_PATH")?;
+                let encoding_key = if algorithm == Algorithm::RS256 {
+                    EncodingKey::from_rsa_pem(&private_pem).context("Invalid RSA private key")?
+                } else {
+                    EncodingKey::from_ed_pem(&private_pem).context("Invalid Ed25519 private key")?
+                };
+                (
+                    encoding_key,
+                    VerificationKey::load(algorithm, &signing_kid, &public_pem, None)?,
+                )
+            }
+        };
+
+        let mut verification_keys = HashMap::new();
+        verification_keys.insert(signing_kid.clone(), active_key);
+
+        // Entries are `kid,retired_at,key` separated by `;`, where `key` is the old
+        // secret for HS256 or the path to the old public key PEM otherwise
+        let retired_keys = env::var("JWT_RETIRED_KEYS").unwrap_or_default();
+        for entry in retired_keys.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut parts = entry.splitn(3, ',');
+            let (Some(kid), Some(retired_at), Some(key)) = (parts.next(), parts.next(), parts.next()) else {
+                bail!("Invalid JWT_RETIRED_KEYS entry (expected kid,retired_at,key): {}", entry);
+            };
+            let kid = kid.trim();
+            if verification_keys.contains_key(kid) {
+                bail!("Duplicate JWT key id: {}", kid);
+            }
+
+            let retired_at = DateTime::parse_from_rfc3339(retired_at.trim())
+                .with_context(|| format!("Invalid retirement time for JWT key {}", kid))?
+                .with_timezone(&Utc);
+            let material = if algorithm == Algorithm::HS256 {
+                key.as_bytes().to_vec()
+            } else {
+                std::fs::read(key.trim())
+                    .with_context(|| format!("Failed to read public key for JWT key {}", kid))?
+            };
+
+            verification_keys.insert(
+                kid.to_string(),
+                VerificationKey::load(algorithm, kid, &material, Some(retired_at + grace_period))?,
+            );
+        }
+
+        let keys = Self {
+            algorithm,
+            signing_kid,
+            encoding_key,
+            verification_keys,
+        };
+        keys.check_key_pair()?;
+        Ok(keys)
+    }
+
+    /// Selects the verification key for a token's `kid` header.
+    ///
+    /// Tokens issued before key ids were introduced have no `kid` and are checked
+    /// against the active key.
+    pub fn decoding_key(&self, kid: Option<&str>) -> Option<&DecodingKey> {
+        self.verification_keys
+            .get(kid.unwrap_or(&self.signing_kid))
+            .filter(|key| key.is_valid())
+            .map(|key| &key.decoding_key)
+    }
+
+    /// Public keys of every key still accepted for verification.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .verification_keys
+                .values()
+                .filter(|key| key.is_valid())
+                .filter_map(|key| key.jwk.clone())
+                .collect(),
+        }
+    }
+
+    /// Fails fast at startup if the configured private and public keys do not belong together.
+    fn check_key_pair(&self) -> anyhow::Result<()> {
+        let probe = Claims {
+            sub: String::new(),
+            exp: usize::MAX,
+            jti: String::new(),
+            scope: String::new(),
+            api_key_id: None,
+            sid: None,
+            act: None,
+        };
+        let token = encode(&Header::new(self.algorithm), &probe, &self.encoding_key)
+            .context("Failed to sign with JWT private key")?;
+        let decoding_key = self.decoding_key(None).context("Active JWT key is missing")?;
+        decode::<Claims>(&token, decoding_key, &Validation::new(self.algorithm))
+            .context("JWT private and public keys do not match")?;
+        Ok(())
+    }
+}
+
+impl VerificationKey {
+    fn load(
+        algorithm: Algorithm,
+        kid: &str,
+        material: &[u8],
+        valid_until: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<Self> {
+        let (decoding_key, jwk) = match algorithm {
+            Algorithm::HS256 => (DecodingKey::from_secret(material), None),
+            Algorithm::RS256 => (
+                DecodingKey::from_rsa_pem(material)
+                    .with_context(|| format!("Invalid RSA public key for JWT key {}", kid))?,
+                Some(public_jwk(material, algorithm, kid)?),
+            ),
+            _ => (
+                DecodingKey::from_ed_pem(material)
+                    .with_context(|| format!("Invalid Ed25519 public key for JWT key {}", kid))?,
+                Some(public_jwk(material, algorithm, kid)?),
+            ),
+        };
+
+        Ok(Self {
+            decoding_key,
+            jwk,
+            valid_until,
+        })
+    }
+
+    fn is_valid(&self) -> bool {
+        self.valid_until.is_none_or(|valid_until| Utc::now() < valid_until)
+    }
+}
+
+fn read_pem_file(var: &str) -> anyhow::Result<Vec<u8>> {
+    let path = env::var(var).with_context(|| format!("{} must be set", var))?;
+    std::fs::read(&path).with_context(|| format!("Failed to read {} ({})", var, path))
+}
+
+/// Builds the public JWK from a `PUBLIC KEY` (SPKI) or `RSA PUBLIC KEY` (PKCS#1) PEM.
+fn public_jwk(public_pem: &[u8], algorithm: Algorithm, kid: &str) -> anyhow::Result<Jwk> {
+    let pem = pem::parse(public_pem).context("Invalid public key PEM")?;
+
+    let key_bytes = if pem.tag() == "RSA PUBLIC KEY" {
+        pem.contents().to_vec()
+    } else {
+        match simple_asn1::from_der(pem.contents())?.first() {
+            Some(ASN1Block::Sequence(_, items)) => match items.get(1) {
+                Some(ASN1Block::BitString(_, _, bytes)) => bytes.clone(),
+                _ => bail!("Public key PEM is not a SubjectPublicKeyInfo"),
+            },
+            _ => bail!("Public key PEM is not a SubjectPublicKeyInfo"),
+        }
+    };
+
+    let (key_algorithm, parameters) = match algorithm {
+        Algorithm::RS256 => {
+            let (n, e) = match simple_asn1::from_der(&key_bytes)?.first() {
+                Some(ASN1Block::Sequence(_, items)) => match (items.first(), items.get(1)) {
+                    (Some(ASN1Block::Integer(_, n)), Some(ASN1Block::Integer(_, e))) => {
+                        (n.to_bytes_be().1, e.to_bytes_be().1)
+                    }
+                    _ => bail!("Invalid RSA public key"),
+                },
+                _ => bail!("Invalid RSA public key"),
+            };
+            (
+                KeyAlgorithm::RS256,
+                AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(n),
+                    e: URL_SAFE_NO_PAD.encode(e),
+                }),
+            )
+        }
+        Algorithm::EdDSA => (
+            KeyAlgorithm::EdDSA,
+            AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(key_bytes),
+            }),
+        ),
+        other => bail!("No public JWK for {:?}", other),
+    };
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(kid.to_string()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    })
+}
+
+/// How new password hashes are computed, set with `PASSWORD_HASH_ALGORITHM` and
+/// the matching `BCRYPT_*` / `ARGON2_*` variables.
+///
+/// Verification always follows the parameters embedded in the stored hash, so
+/// changing the policy never locks anyone out; `login` upgrades outdated hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordHashPolicy {
+    Bcrypt {
+        cost: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl PasswordHashPolicy {
+    fn from_env() -> anyhow::Result<Self> {
+        let policy = match env::var("PASSWORD_HASH_ALGORITHM").as_deref().unwrap_or("bcrypt") {
+            // Cost 8 by default for consistency with the Python implementation
+            "bcrypt" => Self::Bcrypt {
+                cost: env_or("BCRYPT_COST", 8),
+            },
+            // Defaults follow the OWASP minimum recommendation for Argon2id
+            "argon2id" => Self::Argon2id {
+                memory_kib: env_or("ARGON2_MEMORY_KIB", 19456),
+                iterations: env_or("ARGON2_ITERATIONS", 2),
+                parallelism: env_or("ARGON2_PARALLELISM", 1),
+            },
+            other => bail!("Unsupported PASSWORD_HASH_ALGORITHM: {} (expected bcrypt or argon2id)", other),
+        };
+
+        // Fail at startup rather than on the first login
+        policy.hash("calibration").context("Invalid password hashing parameters")?;
+
+        match env::var("PASSWORD_HASH_TARGET_MS").ok().and_then(|v| v.parse().ok()) {
+            Some(target_ms) => Ok(policy.calibrate(Duration::from_millis(target_ms))),
+            None => Ok(policy),
+        }
+    }
+
+    /// Raises the work factor (bcrypt cost or Argon2 iterations) to the highest value
+    /// whose hashing time on this machine stays within `target`. Never goes below
+    /// the configured parameters.
+    fn calibrate(self, target: Duration) -> Self {
+        let mut calibrated = self;
+        loop {
+            let candidate = match calibrated {
+                // 31 is the highest cost bcrypt accepts
+                Self::Bcrypt { cost } if cost < 31 => Self::Bcrypt { cost: cost + 1 },
+                Self::Argon2id {
+                    memory_kib,
+                    iterations,
+                    parallelism,
+                } if iterations < 64 => Self::Argon2id {
+                    memory_kib,
+                    iterations: iterations + 1,
+                    parallelism,
+                },
+                _ => break,
+            };
+
+            let started = Instant::now();
+            if candidate.hash("calibration").is_err() || started.elapsed() > target {
+                break;
+            }
+            calibrated = candidate;
+        }
+
+        tracing::info!(
+            "Password hashing calibrated for {}ms: {:?}",
+            target.as_millis(),
+            calibrated
+        );
+        calibrated
+    }
+
+    fn hash(&self, password: &str) -> Result<String, AppError> {
+        match *self {
+            Self::Bcrypt { cost } => bcrypt::hash(password, cost)
+                .map_err(|_| AppError::InternalServerError("Failed to hash password".to_string())),
+            Self::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let params = argon2::Params::new(memory_kib, iterations, parallelism, None)
+                    .map_err(|_| AppError::InternalServerError("Invalid Argon2 parameters".to_string()))?;
+                let salt = SaltString::generate(&mut OsRng);
+                Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                    .hash_password(password.as_bytes(), &salt)
+                    .map(|hash| hash.to_string())
+                    .map_err(|_| AppError::InternalServerError("Failed to hash password".to_string()))
+            }
+        }
+    }
+
+    /// Whether `hash` was produced with exactly this policy's algorithm and parameters.
+    pub fn is_current(&self, hash: &str) -> bool {
+        match *self {
+            Self::Bcrypt { cost } => hash
+                .parse::<bcrypt::HashParts>()
+                .is_ok_and(|parts| parts.get_cost() == cost),
+            Self::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => PasswordHash::new(hash).is_ok_and(|parsed| {
+                parsed.algorithm == argon2::Algorithm::Argon2id.ident()
+                    && parsed.version == Some(argon2::Version::V0x13.into())
+                    && argon2::Params::try_from(&parsed).is_ok_and(|params| {
+                        params.m_cost() == memory_kib
+                            && params.t_cost() == iterations
+                            && params.p_cost() == parallelism
+                    })
+            }),
         }
     }
 }
 
-pub async fn hash_password(password: &str) -> Result<String, AppError> {
+pub(crate) fn env_or<T: std::str::FromStr>(var: &str, default: T) -> T {
+    env::var(var).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+fn verify_hash(password: &str, hash: &str) -> Result<bool, AppError> {
+    if hash.starts_with("$argon2") {
+        let parsed = PasswordHash::new(hash)
+            .map_err(|_| AppError::InternalServerError("Failed to verify password".to_string()))?;
+        // Parameters are read from the hash itself
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok())
+    } else {
+        bcrypt::verify(password, hash)
+            .map_err(|_| AppError::InternalServerError("Failed to verify password".to_string()))
+    }
+}
+
+pub async fn hash_password(
+    pool: &HashPool,
+    password: &str,
+    policy: PasswordHashPolicy,
+) -> Result<String, AppError> {
     let password = password.to_string();
-    // Using bcrypt with cost 8 for consistency with Python implementation
-    // Offload CPU-intensive bcrypt to a blocking thread to avoid blocking the async runtime
-    tokio::task::spawn_blocking(move || bcrypt::hash(&password, 8))
-        .await
-        .map_err(|_| AppError::InternalServerError("Task join error".to_string()))?
-        .map_err(|_| AppError::InternalServerError("Failed to hash password".to_string()))
+    // Offload CPU-intensive hashing to the hash pool to avoid blocking the async runtime
+    pool.run(move || policy.hash(&password)).await?
 }
 
-pub async fn verify_password(password: &str, hash: &str) -> Result<bool, AppError> {
+pub async fn verify_password(pool: &HashPool, password: &str, hash: &str) -> Result<bool, AppError> {
     let password = password.to_string();
     let hash = hash.to_string();
-    // Offload CPU-intensive bcrypt to a blocking thread to avoid blocking the async runtime
-    tokio::task::spawn_blocking(move || bcrypt::verify(&password, &hash))
-        .await
-        .map_err(|_| AppError::InternalServerError("Task join error".to_string()))?
-        .map_err(|_| AppError::InternalServerError("Failed to verify password".to_string()))
+    // Offload CPU-intensive hashing to the hash pool to avoid blocking the async runtime
+    pool.run(move || verify_hash(&password, &hash)).await?
 }
 
-pub fn create_token(user_id: &Uuid, is_admin: bool, config: &AuthConfig) -> Result<String, AppError> {
+pub fn create_token(
+    user_id: &Uuid,
+    session_id: &Uuid,
+    permissions: &[String],
+    config: &AuthConfig,
+) -> Result<String, AppError> {
     let expiration = chrono::Utc::now()
         .checked_add_signed(chrono::Duration::minutes(config.jwt_expire_minutes))
         .expect("valid timestamp")
@@ -65,28 +605,88 @@ pub fn create_token(user_id: &Uuid, is_admin: bool, config: &AuthConfig) -> Resu
     let claims = Claims {
         sub: user_id.to_string(),
         exp: expiration,
-        is_admin,
+        jti: Uuid::new_v4().to_string(),
+        scope: permissions.join(" "),
+        api_key_id: None,
+        sid: Some(*session_id),
+        act: None,
+    };
+
+    sign_claims(&claims, config)
+}
+
+/// Short-lived token letting an admin act as `user_id`. It carries the user's permissions
+/// and no refresh token is issued with it.
+pub fn create_impersonation_token(
+    user_id: &Uuid,
+    permissions: &[String],
+    admin_id: &Uuid,
+    config: &AuthConfig,
+) -> Result<String, AppError> {
+    let expiration = chrono::Utc::now()
+        .checked_add_signed(chrono::Duration::minutes(config.impersonation_expire_minutes))
+        .expect("valid timestamp")
+        .timestamp() as usize;
+
+    let claims = Claims {
+        sub: user_id.to_string(),
+        exp: expiration,
+        jti: Uuid::new_v4().to_string(),
+        scope: permissions.join(" "),
+        api_key_id: None,
+        sid: None,
+        act: Some(Actor { sub: admin_id.to_string() }),
     };
 
+    sign_claims(&claims, config)
+}
+
+fn sign_claims(claims: &Claims, config: &AuthConfig) -> Result<String, AppError> {
+    let mut header = Header::new(config.jwt_keys.algorithm);
+    header.kid = Some(config.jwt_keys.signing_kid.clone());
+
     encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(config.jwt_secret.as_ref()),
+        &header,
+        claims,
+        &config.jwt_keys.encoding_key,
     )
     .map_err(|_| AppError::InternalServerError("Failed to create token".to_string()))
 }
 
-pub fn decode_token(token: &str, secret: &str) -> Result<Claims, AppError> {
-    let mut validation = Validation::default();
+/// Generates an opaque secret (refresh token, invite code...). Only its hash is ever persisted.
+pub fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Personal access tokens start with this prefix, which tells them apart from JWTs.
+pub const API_KEY_PREFIX: &str = "ak_";
+
+pub fn generate_api_key() -> String {
+    format!("{}{}", API_KEY_PREFIX, generate_opaque_token())
+}
+
+/// SHA-256 is enough here: opaque tokens are 256-bit random values, not passwords.
+pub fn hash_opaque_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn decode_token(token: &str, config: &AuthConfig) -> Result<Claims, AppError> {
+    let mut validation = Validation::new(config.jwt_keys.algorithm);
     validation.validate_exp = true;  // Still validate expiration
     validation.validate_nbf = false; // Skip not-before validation for speed
     validation.validate_aud = false; // Skip audience validation for speed
     
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &validation,
-    )
+    let kid = decode_header(token)
+        .map_err(|_| AppError::Unauthorized("Invalid token".to_string()))?
+        .kid;
+    let decoding_key = config
+        .jwt_keys
+        .decoding_key(kid.as_deref())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token".to_string()))?;
+
+    decode::<Claims>(token, decoding_key, &validation)
     .map(|data| data.claims)
     .map_err(|e| {
         tracing::debug!("Token decode error: {:?}", e);
@@ -108,17 +708,172 @@ pub fn extract_token_from_headers(headers: &HeaderMap) -> Result<String, AppErro
     Ok(auth_header[7..].to_string())
 }
 
-// Middleware for extracting user ID from JWT token
+/// The authenticated caller. Handlers take it as an extractor instead of reading `Claims`.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: Uuid,
+    pub claims: Claims,
+}
+
+impl AuthUser {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.claims.has_permission(permission)
+    }
+
+    /// The admin behind an impersonation token, if any.
+    pub fn impersonator_id(&self) -> Option<Uuid> {
+        self.claims.act.as_ref().and_then(|act| Uuid::parse_str(&act.sub).ok())
+    }
+}
+
+/// A caller allowed to manage users (`users:write`), the permission that sets admins apart
+/// from moderators. Handlers taking it cannot run for anyone else, whatever the routing.
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthUser);
+
+/// Bearer credentials take precedence; the session cookie is only read without them.
+async fn authenticate(method: &Method, headers: &HeaderMap, app_state: &AppState) -> Result<AuthUser, AppError> {
+    let token = if headers.contains_key(AUTHORIZATION) {
+        extract_token_from_headers(headers)?
+    } else {
+        let jar = CookieJar::from_headers(headers);
+        let session = jar
+            .get(SESSION_COOKIE)
+            .ok_or_else(|| AppError::Unauthorized("Missing authorization header".to_string()))?;
+        verify_csrf(method, headers, &jar)?;
+        session.value().to_string()
+    };
+
+    let claims = if token.starts_with(API_KEY_PREFIX) {
+        authenticate_api_key(&app_state.db, &token).await?
+    } else {
+        let claims = decode_token(&token, &app_state.auth_config)?;
+        let revoked_tokens = &app_state.revoked_tokens;
+        if revoked_tokens.is_revoked(&claims.jti)
+            || claims.sid.is_some_and(|sid| revoked_tokens.is_revoked(&sid.to_string()))
+        {
+            return Err(AppError::Unauthorized("Token has been revoked".to_string()));
+        }
+        claims
+    };
+
+    let id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| AppError::Unauthorized("Invalid token".to_string()))?;
+
+    // Deleting a user denylists their sessions, but impersonation tokens have none
+    if claims.act.is_some() {
+        let active: bool = sqlx::query_scalar(SQL_IS_USER_ACTIVE)
+            .bind(id)
+            .fetch_one(&app_state.db)
+            .await?;
+        if !active {
+            return Err(AppError::Unauthorized("User not found".to_string()));
+        }
+    }
+    Ok(AuthUser { id, claims })
+}
+
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, app_state: &AppState) -> Result<Self, Self::Rejection> {
+        // Already verified by auth_middleware on protected routes
+        if let Some(user) = parts.extensions.get::<AuthUser>() {
+            return Ok(user.clone());
+        }
+
+        let user = authenticate(&parts.method, &parts.headers, app_state).await?;
+        parts.extensions.insert(user.clone());
+        Ok(user)
+    }
+}
+
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, app_state: &AppState) -> Result<Self, Self::Rejection> {
+        let user = <AuthUser as FromRequestParts<AppState>>::from_request_parts(parts, app_state).await?;
+        if !user.has_permission(Permission::UsersWrite) {
+            return Err(AppError::Forbidden("Admin access required".to_string()));
+        }
+        Ok(AdminUser(user))
+    }
+}
+
+// Middleware verifying the token once per request, handlers then extract `AuthUser`
 pub async fn auth_middleware(
-    State(auth_config): State<AuthConfig>,
+    State(app_state): State<AppState>,
     mut request: Request,
     next: Next,
 ) -> Result<Response, AppError> {
-    let token = extract_token_from_headers(request.headers())?;
-    let claims = decode_token(&token, &auth_config.jwt_secret)?;
-    
-    // Add claims to request extensions for use in handlers
-    request.extensions_mut().insert(claims);
-    
+    let user = authenticate(request.method(), request.headers(), &app_state).await?;
+    let impersonation = user.impersonator_id().map(|admin_id| AuditEntry {
+        actor_id: admin_id,
+        user_id: user.id,
+        method: request.method().to_string(),
+        path: request.uri().path().to_string(),
+    });
+
+    // Add the caller to request extensions for use in handlers
+    request.extensions_mut().insert(user);
+
+    let response = next.run(request).await;
+    if let Some(entry) = impersonation {
+        audit::record(&app_state.db, entry, response.status()).await;
+    }
+    Ok(response)
+}
+
+/// Route layer for credential management (password, MFA, API keys), which stays out of
+/// reach of impersonation tokens.
+pub async fn forbid_impersonation(request: Request, next: Next) -> Result<Response, AppError> {
+    let impersonated = request
+        .extensions()
+        .get::<AuthUser>()
+        .is_some_and(|user| user.claims.act.is_some());
+    if impersonated {
+        return Err(AppError::Forbidden("Not allowed while impersonating".to_string()));
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// API keys are looked up on every request, so revocation and role changes apply immediately.
+async fn authenticate_api_key(db: &PgPool, key: &str) -> Result<Claims, AppError> {
+    let key_row: Option<ApiKeyAuthRow> = sqlx::query_as(SQL_AUTHENTICATE_API_KEY)
+        .bind(hash_opaque_token(key))
+        .fetch_optional(db)
+        .await?;
+    let key_row = key_row.ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?;
+
+    Ok(Claims {
+        sub: key_row.user_id.to_string(),
+        exp: key_row
+            .expires_at
+            .map_or(usize::MAX, |expires_at| expires_at.timestamp() as usize),
+        jti: key_row.id.to_string(),
+        scope: key_row.permissions.join(" "),
+        api_key_id: Some(key_row.id),
+        sid: None,
+        act: None,
+    })
+}
+
+/// Route layer enforcing a permission, applied after `auth_middleware`:
+/// `.route_layer(middleware::from_fn_with_state(Permission::UsersRead, require_permission))`
+pub async fn require_permission(
+    State(permission): State<Permission>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let user = request
+        .extensions()
+        .get::<AuthUser>()
+        .ok_or_else(|| AppError::Unauthorized("Missing authorization header".to_string()))?;
+
+    if !user.has_permission(permission) {
+        return Err(AppError::Forbidden(format!("Missing permission: {}", permission.as_str())));
+    }
+
     Ok(next.run(request).await)
 }
@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -24,6 +24,15 @@ pub enum AppError {
     
     #[error("Conflict: {0}")]
     Conflict(String),
+
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
+
+    #[error("Too many requests: {message}")]
+    TooManyRequests {
+        message: String,
+        retry_after_secs: u64,
+    },
     
     #[error("Internal server error: {0}")]
     InternalServerError(String),
@@ -49,6 +58,12 @@ impl IntoResponse for AppError {
             AppError::NotFound(ref message) => (StatusCode::NOT_FOUND, message.as_str()),
             AppError::BadRequest(ref message) => (StatusCode::BAD_REQUEST, message.as_str()),
             AppError::Conflict(ref message) => (StatusCode::CONFLICT, message.as_str()),
+            AppError::ServiceUnavailable(ref message) => {
+                (StatusCode::SERVICE_UNAVAILABLE, message.as_str())
+            }
+            AppError::TooManyRequests { ref message, .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, message.as_str())
+            }
             AppError::InternalServerError(ref message) => {
                 tracing::error!("Internal server error: {}", message);
                 (StatusCode::INTERNAL_SERVER_ERROR, message.as_str())
@@ -59,6 +74,18 @@ impl IntoResponse for AppError {
             "detail": error_message,
         }));
 
+        if let AppError::TooManyRequests {
+            retry_after_secs, ..
+        } = self
+        {
+            return (
+                status,
+                [(header::RETRY_AFTER, retry_after_secs.to_string())],
+                body,
+            )
+                .into_response();
+        }
+
         (status, body).into_response()
     }
 }
@@ -0,0 +1,228 @@
+use anyhow::{anyhow, Context};
+use axum::{
+    body::{Body, Bytes},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use futures_util::{stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgRow, FromRow, PgConnection, PgPool};
+use std::{
+    io::{self, Write},
+    mem,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+use zip::{
+    write::{SimpleFileOptions, StreamWriter},
+    ZipWriter,
+};
+
+use crate::{
+    models::{Comment, CommentRow, Like, LikeRow, Post, PostRow, User, UserRow},
+    sql::{SQL_LIST_COMMENTS_BY_AUTHOR, SQL_LIST_LIKES_BY_USER, SQL_LIST_POSTS_BY_AUTHOR},
+};
+
+/// Bytes buffered before they are handed to the response body
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Chunks waiting for a slow client before the export stops reading rows
+const CHUNKS_IN_FLIGHT: usize = 4;
+/// Longest an export may hold its pooled connection, however slowly the client reads
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One document: `{"profile": {...}, "posts": [...], "comments": [...], "likes": [...]}`
+    #[default]
+    Json,
+    /// `profile.json`, `posts.json`, `comments.json` and `likes.json`
+    Zip,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Everything stored about a user, streamed as a download.
+///
+/// Rows are read one at a time from a single read-only snapshot and written to the body
+/// as they arrive, so large accounts are never held in memory. Headers are sent before
+/// the first row: a failure after that point is logged and aborts the body, which the
+/// client sees as a truncated download rather than a complete-looking file. The snapshot
+/// keeps a pooled connection busy, so an export still running after `EXPORT_TIMEOUT`
+/// (typically a client reading very slowly) is aborted the same way.
+pub fn export_response(db: PgPool, user: UserRow, format: ExportFormat) -> Response {
+    let (sender, mut receiver) = mpsc::channel::<io::Result<Bytes>>(CHUNKS_IN_FLIGHT);
+    let (content_type, extension) = match format {
+        ExportFormat::Json => ("application/json", "json"),
+        ExportFormat::Zip => ("application/zip", "zip"),
+    };
+    let disposition = format!("attachment; filename=\"export-{}.{}\"", user.id, extension);
+
+    tokio::spawn(async move {
+        let user_id = user.id;
+        let result = tokio::time::timeout(EXPORT_TIMEOUT, write_export(&db, user, format, &sender))
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("Timed out after {:?}", EXPORT_TIMEOUT)));
+        if let Err(e) = result {
+            if sender.is_closed() {
+                tracing::debug!("Export of user {} abandoned by the client", user_id);
+            } else {
+                tracing::error!("Export of user {} failed: {:#}", user_id, e);
+                let _ = sender.send(Err(io::Error::other("Export failed"))).await;
+            }
+        }
+    });
+
+    let body = Body::from_stream(stream::poll_fn(move |cx| receiver.poll_recv(cx)));
+    ([(CONTENT_TYPE, content_type.to_string()), (CONTENT_DISPOSITION, disposition)], body).into_response()
+}
+
+async fn write_export(
+    db: &PgPool,
+    user: UserRow,
+    format: ExportFormat,
+    sender: &mpsc::Sender<io::Result<Bytes>>,
+) -> anyhow::Result<()> {
+    let buffer = ChunkBuffer::default();
+    let mut archive = match format {
+        ExportFormat::Json => Archive::Json { out: buffer.clone(), sections: 0 },
+        ExportFormat::Zip => Archive::Zip(Box::new(ZipWriter::new_stream(buffer.clone()))),
+    };
+    let user_id = user.id;
+
+    // Posts liked meanwhile must not be missing from one list and counted in another
+    let mut tx = db
+        .begin_with("BEGIN ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .await?;
+
+    archive.start_section("profile")?;
+    serde_json::to_writer(archive.writer(), &User::from(user))?;
+
+    write_rows::<PostRow, Post>(&mut tx, SQL_LIST_POSTS_BY_AUTHOR, user_id, "posts", &mut archive, &buffer, sender)
+        .await?;
+    write_rows::<CommentRow, Comment>(
+        &mut tx,
+        SQL_LIST_COMMENTS_BY_AUTHOR,
+        user_id,
+        "comments",
+        &mut archive,
+        &buffer,
+        sender,
+    )
+    .await?;
+    write_rows::<LikeRow, Like>(&mut tx, SQL_LIST_LIKES_BY_USER, user_id, "likes", &mut archive, &buffer, sender)
+        .await?;
+
+    tx.commit().await?;
+    archive.finish()?;
+    send_chunk(&buffer, 0, sender).await
+}
+
+/// Writes one section as a JSON array, one row at a time.
+async fn write_rows<R, T>(
+    conn: &mut PgConnection,
+    sql: &'static str,
+    user_id: Uuid,
+    section: &str,
+    archive: &mut Archive,
+    buffer: &ChunkBuffer,
+    sender: &mpsc::Sender<io::Result<Bytes>>,
+) -> anyhow::Result<()>
+where
+    R: for<'r> FromRow<'r, PgRow> + Send + Unpin,
+    T: From<R> + Serialize,
+{
+    archive.start_section(section)?;
+    archive.writer().write_all(b"[")?;
+
+    let mut rows = sqlx::query_as::<_, R>(sql).bind(user_id).fetch(&mut *conn);
+    let mut first = true;
+    while let Some(row) = rows.try_next().await? {
+        if !first {
+            archive.writer().write_all(b",")?;
+        }
+        first = false;
+        serde_json::to_writer(archive.writer(), &T::from(row))?;
+        send_chunk(buffer, CHUNK_SIZE, sender).await?;
+    }
+
+    archive.writer().write_all(b"]")?;
+    Ok(())
+}
+
+/// Hands the buffered bytes to the response body once there are at least `min_len` of them.
+async fn send_chunk(
+    buffer: &ChunkBuffer,
+    min_len: usize,
+    sender: &mpsc::Sender<io::Result<Bytes>>,
+) -> anyhow::Result<()> {
+    if let Some(chunk) = buffer.take(min_len) {
+        sender.send(Ok(chunk)).await.ok().context("Export body was dropped")?;
+    }
+    Ok(())
+}
+
+enum Archive {
+    Json { out: ChunkBuffer, sections: usize },
+    Zip(Box<ZipWriter<StreamWriter<ChunkBuffer>>>),
+}
+
+impl Archive {
+    fn start_section(&mut self, name: &str) -> io::Result<()> {
+        match self {
+            Archive::Json { out, sections } => {
+                out.write_all(if *sections == 0 { b"{" } else { b"," })?;
+                serde_json::to_writer(&mut *out, name)?;
+                out.write_all(b":")?;
+                *sections += 1;
+            }
+            Archive::Zip(zip) => zip.start_file(format!("{}.json", name), SimpleFileOptions::default())?,
+        }
+        Ok(())
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Archive::Json { out, .. } => out,
+            Archive::Zip(zip) => zip.as_mut(),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Archive::Json { mut out, .. } => out.write_all(b"}"),
+            Archive::Zip(zip) => zip.finish().map(|_| ()).map_err(Into::into),
+        }
+    }
+}
+
+/// Written to by the archive, drained into the response body between rows.
+#[derive(Clone, Default)]
+struct ChunkBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl ChunkBuffer {
+    fn take(&self, min_len: usize) -> Option<Bytes> {
+        let mut bytes = self.0.lock().unwrap();
+        if bytes.is_empty() || bytes.len() < min_len {
+            return None;
+        }
+        Some(Bytes::from(mem::take(&mut *bytes)))
+    }
+}
+
+impl Write for ChunkBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,15 +1,39 @@
 use axum::{
-    extract::{Path, Query, State, Extension},
-    http::StatusCode,
+    body::{Body, Bytes},
+    extract::{rejection::JsonRejection, ConnectInfo, FromRequest, Path, Query, Request, State},
+    http::{header::{CONTENT_TYPE, USER_AGENT}, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
     Json,
 };
-use serde::Deserialize;
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use serde::{de::DeserializeOwned, Deserialize};
+use sqlx::{PgConnection, PgExecutor};
+use std::net::SocketAddr;
 use uuid::Uuid;
 
 use crate::{
-    auth::{create_token, hash_password, verify_password, Claims},
+    audit::{self, AuditEntry},
+    auth::{
+        create_impersonation_token, create_token, generate_api_key, generate_opaque_token, hash_opaque_token, hash_password,
+        verify_password, AdminUser, AuthConfig, AuthUser, Permission, RegistrationMode,
+        API_KEY_PREFIX,
+    },
     error::AppError,
+    export::{export_response, ExportQuery},
+    hash_pool::HashPoolStats,
+    import::{self, ImportFormat},
+    mfa::{
+        generate_recovery_codes, generate_totp_secret, is_totp_code, normalize_recovery_code,
+        otpauth_uri, verify_totp, MFA_CHALLENGE_EXPIRE_MINUTES, MFA_MAX_ATTEMPTS,
+    },
     models::*,
+    oidc::{IdTokenClaims, OidcClient},
+    session::{
+        clear_oidc_state_cookie, clear_session_cookies, set_oidc_state_cookie, set_session_cookies,
+        verify_csrf, verify_oidc_state, REFRESH_COOKIE, SESSION_COOKIE,
+    },
     sql::*,
     AppState,
 };
@@ -26,65 +50,1433 @@ fn default_limit() -> i64 {
     20
 }
 
+/// How `login` and `refresh` hand out tokens: in the JSON body, or as session cookies
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenDelivery {
+    #[default]
+    Bearer,
+    Cookie,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenDeliveryQuery {
+    #[serde(default)]
+    pub mode: TokenDelivery,
+}
+
+/// Optional JSON body. Unlike `Option<Json<T>>`, an empty body is `None` even when sent as
+/// `Content-Type: application/json`, which many clients do for a POST without a payload.
+pub struct OptionalJson<T>(pub Option<T>);
+
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for OptionalJson<T> {
+    type Rejection = JsonRejection;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let (parts, body) = request.into_parts();
+        let bytes = Bytes::from_request(Request::from_parts(parts.clone(), body), state).await?;
+        if bytes.is_empty() {
+            return Ok(Self(None));
+        }
+
+        let request = Request::from_parts(parts, Body::from(bytes));
+        let json = Option::<Json<T>>::from_request(request, state).await?;
+        Ok(Self(json.map(|Json(value)| value)))
+    }
+}
+
+fn token_response(
+    delivery: TokenDelivery,
+    jar: CookieJar,
+    tokens: LoginResponse,
+    config: &AuthConfig,
+) -> Response {
+    match delivery {
+        TokenDelivery::Bearer => Json(tokens).into_response(),
+        // Tokens stay out of the body so scripts never see them
+        TokenDelivery::Cookie => {
+            (set_session_cookies(jar, tokens, config), StatusCode::NO_CONTENT).into_response()
+        }
+    }
+}
+
+async fn issue_refresh_token<'e, E: PgExecutor<'e>>(
+    executor: E,
+    user_id: Uuid,
+    family_id: Uuid,
+    config: &AuthConfig,
+) -> Result<String, AppError> {
+    let refresh_token = generate_opaque_token();
+    let expires_at = Utc::now() + chrono::Duration::days(config.refresh_token_expire_days);
+
+    sqlx::query(SQL_CREATE_REFRESH_TOKEN)
+        .bind(user_id)
+        .bind(family_id)
+        .bind(hash_opaque_token(&refresh_token))
+        .bind(expires_at)
+        .execute(executor)
+        .await?;
+
+    Ok(refresh_token)
+}
+
+/// Longest device description kept for a session
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// Starts a login session and issues its first token pair. The session id is also the
+/// refresh token family and the `sid` claim of the access tokens.
+async fn start_session(
+    app_state: &AppState,
+    user_id: Uuid,
+    permissions: &[String],
+    headers: &HeaderMap,
+    peer: SocketAddr,
+) -> Result<LoginResponse, AppError> {
+    let config = &app_state.auth_config;
+    let session_id = Uuid::new_v4();
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(MAX_USER_AGENT_LEN).collect::<String>());
+    let ip_address = app_state.login_throttle.client_ip(headers, peer);
+
+    let mut tx = app_state.db.begin().await?;
+
+    sqlx::query(SQL_CREATE_SESSION)
+        .bind(session_id)
+        .bind(user_id)
+        .bind(user_agent)
+        .bind(ip_address.to_string())
+        .bind(Utc::now() + chrono::Duration::days(config.refresh_token_expire_days))
+        .execute(&mut *tx)
+        .await?;
+
+    let refresh_token = issue_refresh_token(&mut *tx, user_id, session_id, config).await?;
+
+    tx.commit().await?;
+
+    Ok(LoginResponse {
+        access_token: create_token(&user_id, &session_id, permissions, config)?,
+        refresh_token,
+    })
+}
+
+/// Ends every session of the user and their refresh tokens. The returned session ids
+/// still need `revoke_session_tokens` once the transaction is committed.
+async fn end_user_sessions(conn: &mut PgConnection, user_id: Uuid) -> Result<Vec<Uuid>, AppError> {
+    let session_ids: Vec<Uuid> = sqlx::query_scalar(SQL_REVOKE_USER_SESSIONS)
+        .bind(user_id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    sqlx::query(SQL_REVOKE_USER_REFRESH_TOKENS)
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(session_ids)
+}
+
+/// Denylists the access tokens of ended sessions, until the last of them would have expired.
+async fn revoke_session_tokens(app_state: &AppState, user_id: Uuid, session_ids: &[Uuid]) -> Result<(), AppError> {
+    let expires_at =
+        Utc::now() + chrono::Duration::minutes(app_state.auth_config.jwt_expire_minutes);
+    for session_id in session_ids {
+        app_state
+            .revoked_tokens
+            .revoke(&session_id.to_string(), user_id, expires_at)
+            .await?;
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Auth endpoints
+////////////////////////////////////////////////////////////////////////////////
+
+pub async fn login(
+    State(app_state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Query(query): Query<TokenDeliveryQuery>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(credentials): Json<LoginCredentials>,
+) -> Result<Response, AppError> {
+    let throttle = &app_state.login_throttle;
+    let account = credentials.email.to_lowercase();
+    let client_ip = throttle.client_ip(&headers, peer);
+
+    // Checked before any hashing so locked-out attempts cost no CPU
+    let attempt = throttle.check(&account, client_ip)?;
+
+    let login_row: Option<LoginRow> = sqlx::query_as(SQL_LOGIN_WITH_PERMISSIONS)
+        .bind(&credentials.email)
+        .fetch_optional(&app_state.db)
+        .await?;
+
+    let Some(row) = login_row else {
+        // Spend the same time as a real verification so unknown emails are not distinguishable
+        verify_password(
+            &app_state.hash_pool,
+            &credentials.password,
+            &app_state.auth_config.dummy_password_hash,
+        )
+        .await?;
+        attempt.record_failure();
+        return Err(AppError::Unauthorized("Invalid credentials".to_string()));
+    };
+
+    let is_valid =
+        verify_password(&app_state.hash_pool, &credentials.password, &row.password_hash).await?;
+    if !is_valid {
+        attempt.record_failure();
+        return Err(AppError::Unauthorized("Invalid credentials".to_string()));
+    }
+
+    if !app_state.auth_config.password_policy.is_current(&row.password_hash) {
+        spawn_rehash(&app_state, row.id, credentials.password, row.password_hash);
+    }
+
+    if row.mfa_enabled {
+        // Throttle counters are only cleared once the second factor is verified too
+        let mfa_token = generate_opaque_token();
+        let expires_at = Utc::now() + chrono::Duration::minutes(MFA_CHALLENGE_EXPIRE_MINUTES);
+        sqlx::query(SQL_CREATE_MFA_CHALLENGE)
+            .bind(row.id)
+            .bind(hash_opaque_token(&mfa_token))
+            .bind(matches!(query.mode, TokenDelivery::Cookie))
+            .bind(expires_at)
+            .execute(&app_state.db)
+            .await?;
+
+        return Ok(Json(MfaChallenge {
+            mfa_required: true,
+            mfa_token,
+            expires_at,
+        })
+        .into_response());
+    }
+
+    attempt.record_success();
+
+    let tokens = start_session(&app_state, row.id, &row.permissions, &headers, peer).await?;
+
+    Ok(token_response(query.mode, jar, tokens, &app_state.auth_config))
+}
+
+/// Second login step for TOTP users: exchanges the "mfa pending" token and a code for tokens.
+pub async fn login_mfa(
+    State(app_state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Json(request): Json<MfaLogin>,
+) -> Result<Response, AppError> {
+    let challenge: Option<MfaChallengeRow> = sqlx::query_as(SQL_GET_MFA_CHALLENGE)
+        .bind(hash_opaque_token(&request.mfa_token))
+        .bind(MFA_MAX_ATTEMPTS)
+        .fetch_optional(&app_state.db)
+        .await?;
+    let challenge = challenge
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired MFA token".to_string()))?;
+
+    let user_row: UserRow = sqlx::query_as(SQL_ME)
+        .bind(challenge.user_id)
+        .fetch_optional(&app_state.db)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired MFA token".to_string()))?;
+    let throttle = &app_state.login_throttle;
+    let account = user_row.email.to_lowercase();
+    let client_ip = throttle.client_ip(&headers, peer);
+    let attempt = throttle.check(&account, client_ip)?;
+
+    if !verify_second_factor(&app_state, challenge.user_id, &request.code).await? {
+        sqlx::query(SQL_FAIL_MFA_CHALLENGE)
+            .bind(challenge.id)
+            .execute(&app_state.db)
+            .await?;
+        attempt.record_failure();
+        return Err(AppError::Unauthorized("Invalid code".to_string()));
+    }
+
+    // Single use, even if the same token is presented twice concurrently
+    let completed = sqlx::query(SQL_COMPLETE_MFA_CHALLENGE)
+        .bind(challenge.id)
+        .execute(&app_state.db)
+        .await?;
+    if completed.rows_affected() == 0 {
+        return Err(AppError::Unauthorized("Invalid or expired MFA token".to_string()));
+    }
+
+    attempt.record_success();
+
+    let permissions: Vec<String> = sqlx::query_scalar(SQL_GET_USER_PERMISSIONS)
+        .bind(challenge.user_id)
+        .fetch_one(&app_state.db)
+        .await?;
+    let tokens = start_session(&app_state, challenge.user_id, &permissions, &headers, peer).await?;
+
+    let delivery = if challenge.cookie_mode {
+        TokenDelivery::Cookie
+    } else {
+        TokenDelivery::Bearer
+    };
+    Ok(token_response(delivery, jar, tokens, &app_state.auth_config))
+}
+
+/// Upgrades a hash made with an outdated policy. Runs in the background so the
+/// login response does not wait for a second hash.
+fn spawn_rehash(app_state: &AppState, user_id: Uuid, password: String, old_hash: String) {
+    let db = app_state.db.clone();
+    let hash_pool = app_state.hash_pool.clone();
+    let policy = app_state.auth_config.password_policy;
+
+    tokio::spawn(async move {
+        let result = async {
+            let new_hash = hash_password(&hash_pool, &password, policy).await?;
+            sqlx::query(SQL_REHASH_USER_PASSWORD)
+                .bind(user_id)
+                .bind(&old_hash)
+                .bind(&new_hash)
+                .execute(&db)
+                .await?;
+            Ok::<_, AppError>(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            tracing::warn!("Failed to rehash password for user {}: {}", user_id, e);
+        }
+    });
+}
+
+pub async fn register(
+    State(app_state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(registration): Json<RegisterUser>,
+) -> Result<(StatusCode, Json<LoginResponse>), AppError> {
+    let mode = app_state.auth_config.registration_mode;
+    if mode == RegistrationMode::Disabled {
+        return Err(AppError::Forbidden("Registration is disabled".to_string()));
+    }
+
+    let invite_code = match (mode, registration.invite_code.as_deref()) {
+        (RegistrationMode::InviteOnly, None) => {
+            return Err(AppError::Forbidden("An invite code is required".to_string()));
+        }
+        (RegistrationMode::InviteOnly, Some(code)) => Some(code),
+        _ => None,
+    };
+
+    let password_hash = hash_password(
+        &app_state.hash_pool,
+        &registration.password,
+        app_state.auth_config.password_policy,
+    )
+    .await?;
+
+    let mut tx = app_state.db.begin().await?;
+
+    let created_id: Uuid = sqlx::query_scalar(SQL_CREATE_USER)
+        .bind(&registration.username)
+        .bind(&registration.email)
+        .bind(&password_hash)
+        .bind(None::<String>) // bio is None for new users
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| {
+            if let Some(db_err) = e.as_database_error() {
+                // 23505: unique_violation
+                if db_err.code().as_deref() == Some("23505") {
+                    return AppError::Conflict("Username or email already exists".to_string());
+                }
+            }
+            AppError::BadRequest("Failed to create user".to_string())
+        })?;
+
+    if let Some(code) = invite_code {
+        let invite_id: Option<Uuid> = sqlx::query_scalar(SQL_CONSUME_INVITE)
+            .bind(hash_opaque_token(code))
+            .bind(&registration.email)
+            .bind(created_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        if invite_id.is_none() {
+            tx.rollback().await?;
+            return Err(AppError::Forbidden("Invalid invite code".to_string()));
+        }
+    }
+
+    tx.commit().await?;
+
+    // Self-registered users start without any role
+    let tokens = start_session(&app_state, created_id, &[], &headers, peer).await?;
+
+    Ok((StatusCode::CREATED, Json(tokens)))
+}
+
+pub async fn create_invite(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    Json(invite_data): Json<InviteCreate>,
+) -> Result<(StatusCode, Json<Invite>), AppError> {
+    let invite_code = generate_opaque_token();
+    let expires_at =
+        Utc::now() + chrono::Duration::hours(app_state.auth_config.invite_expire_hours);
+
+    sqlx::query(SQL_CREATE_INVITE)
+        .bind(hash_opaque_token(&invite_code))
+        .bind(invite_data.email.as_deref())
+        .bind(user.id)
+        .bind(expires_at)
+        .execute(&app_state.db)
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(Invite {
+            invite_code,
+            email: invite_data.email,
+            expires_at,
+        }),
+    ))
+}
+
+/// Takes the refresh token from the body, or from the refresh cookie when there is no body.
+pub async fn refresh(
+    State(app_state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    OptionalJson(request): OptionalJson<RefreshRequest>,
+) -> Result<Response, AppError> {
+    let (presented_token, delivery) = match request {
+        Some(request) => (request.refresh_token, TokenDelivery::Bearer),
+        None => {
+            let cookie = jar
+                .get(REFRESH_COOKIE)
+                .ok_or_else(|| AppError::Unauthorized("Missing refresh token".to_string()))?;
+            verify_csrf(&Method::POST, &headers, &jar)?;
+            (cookie.value().to_string(), TokenDelivery::Cookie)
+        }
+    };
+
+    let token_row: Option<RefreshTokenRow> = sqlx::query_as(SQL_GET_REFRESH_TOKEN)
+        .bind(hash_opaque_token(&presented_token))
+        .fetch_optional(&app_state.db)
+        .await?;
+
+    let token_row =
+        token_row.ok_or_else(|| AppError::Unauthorized("Invalid refresh token".to_string()))?;
+
+    if token_row.revoked_at.is_some() {
+        // A rotated token was replayed: assume it leaked and revoke the whole family
+        tracing::warn!(
+            "Refresh token reuse detected for user {}, revoking family {}",
+            token_row.user_id,
+            token_row.family_id
+        );
+        end_replayed_session(&app_state, &token_row).await?;
+        return Err(AppError::Unauthorized("Invalid refresh token".to_string()));
+    }
+
+    if token_row.expires_at <= Utc::now() {
+        return Err(AppError::Unauthorized("Refresh token expired".to_string()));
+    }
+
+    let mut tx = app_state.db.begin().await?;
+
+    let revoked = sqlx::query(SQL_REVOKE_REFRESH_TOKEN)
+        .bind(token_row.id)
+        .execute(&mut *tx)
+        .await?;
+
+    if revoked.rows_affected() == 0 {
+        // Another request rotated this token first, which is a replay as well
+        tx.rollback().await?;
+        end_replayed_session(&app_state, &token_row).await?;
+        return Err(AppError::Unauthorized("Invalid refresh token".to_string()));
+    }
+
+    let refresh_token = issue_refresh_token(
+        &mut *tx,
+        token_row.user_id,
+        token_row.family_id,
+        &app_state.auth_config,
+    )
+    .await?;
+
+    // The refresh token family is the session
+    sqlx::query(SQL_TOUCH_SESSION)
+        .bind(token_row.family_id)
+        .bind(app_state.login_throttle.client_ip(&headers, peer).to_string())
+        .bind(Utc::now() + chrono::Duration::days(app_state.auth_config.refresh_token_expire_days))
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    let access_token = create_token(
+        &token_row.user_id,
+        &token_row.family_id,
+        &token_row.permissions,
+        &app_state.auth_config,
+    )?;
+
+    Ok(token_response(
+        delivery,
+        jar,
+        LoginResponse {
+            access_token,
+            refresh_token,
+        },
+        &app_state.auth_config,
+    ))
+}
+
+/// A replayed refresh token means the session leaked: its refresh tokens are revoked, and
+/// the session ends so that its access tokens stop working too.
+async fn end_replayed_session(app_state: &AppState, token_row: &RefreshTokenRow) -> Result<(), AppError> {
+    // The family is revoked even if the session had already ended some other way
+    sqlx::query(SQL_REVOKE_REFRESH_TOKEN_FAMILY)
+        .bind(token_row.family_id)
+        .execute(&app_state.db)
+        .await?;
+
+    end_session(app_state, token_row.user_id, token_row.family_id).await?;
+    Ok(())
+}
+
+pub async fn logout(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    jar: CookieJar,
+    OptionalJson(request): OptionalJson<LogoutRequest>,
+) -> Result<(CookieJar, StatusCode), AppError> {
+    if user.claims.api_key_id.is_some() {
+        return Err(AppError::BadRequest(
+            "API keys are revoked with DELETE /auth/api-keys/{keyId}".to_string(),
+        ));
+    }
+
+    let expires_at = DateTime::from_timestamp(user.claims.exp as i64, 0)
+        .ok_or_else(|| AppError::BadRequest("Invalid token expiration".to_string()))?;
+
+    app_state
+        .revoked_tokens
+        .revoke(&user.claims.jti, user.id, expires_at)
+        .await?;
+
+    if let Some(session_id) = user.claims.sid {
+        end_session(&app_state, user.id, session_id).await?;
+    }
+
+    // Optionally end the refresh token family as well, so the session cannot be renewed
+    let refresh_token = request
+        .and_then(|request| request.refresh_token)
+        .or_else(|| jar.get(REFRESH_COOKIE).map(|cookie| cookie.value().to_string()));
+    if let Some(refresh_token) = refresh_token {
+        let token_row: Option<RefreshTokenRow> = sqlx::query_as(SQL_GET_REFRESH_TOKEN)
+            .bind(hash_opaque_token(&refresh_token))
+            .fetch_optional(&app_state.db)
+            .await?;
+
+        if let Some(row) = token_row.filter(|row| row.user_id == user.id) {
+            sqlx::query(SQL_REVOKE_REFRESH_TOKEN_FAMILY)
+                .bind(row.family_id)
+                .execute(&app_state.db)
+                .await?;
+        }
+    }
+
+    let jar = if jar.get(SESSION_COOKIE).is_some() {
+        clear_session_cookies(jar)
+    } else {
+        jar
+    };
+    Ok((jar, StatusCode::NO_CONTENT))
+}
+
+pub async fn jwks(State(app_state): State<AppState>) -> Json<JwkSet> {
+    Json(app_state.auth_config.jwt_keys.jwks())
+}
+
+pub async fn me(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<CurrentUser>, AppError> {
+    let user_row: Option<UserRow> = sqlx::query_as(SQL_ME)
+        .bind(user.id)
+        .fetch_optional(&app_state.db)
+        .await?;
+
+    match user_row {
+        Some(row) => Ok(Json(CurrentUser {
+            user: User::from(row),
+            impersonated_by: user.impersonator_id().map(|id| id.to_string()),
+        })),
+        None => Err(AppError::Unauthorized("User not found".to_string())),
+    }
+}
+
+pub async fn update_me(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    Json(update): Json<MeUpdate>,
+) -> Result<Json<MeUpdated>, AppError> {
+    for (field, is_null) in [
+        ("username", update.username == Patch::Null),
+        ("email", update.email == Patch::Null),
+    ] {
+        if is_null {
+            return Err(AppError::BadRequest(format!("{} cannot be null", field)));
+        }
+    }
+    if let Patch::Value(username) = &update.username {
+        if username.trim().is_empty() || username.len() > 255 {
+            return Err(AppError::BadRequest("Username must be 1 to 255 characters".to_string()));
+        }
+    }
+
+    let current: UserRow = sqlx::query_as(SQL_ME)
+        .bind(user.id)
+        .fetch_optional(&app_state.db)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
+
+    let new_email = match update.email {
+        Patch::Value(email) if email != current.email => Some(email),
+        _ => None,
+    };
+    if let Some(email) = &new_email {
+        if user.impersonator_id().is_some() {
+            return Err(AppError::Forbidden("Not allowed while impersonating".to_string()));
+        }
+        if email.len() > 255 || !email.contains('@') {
+            return Err(AppError::BadRequest("Invalid email".to_string()));
+        }
+        let owner: Option<UserRow> = sqlx::query_as(SQL_GET_USER_BY_EMAIL)
+            .bind(email)
+            .fetch_optional(&app_state.db)
+            .await?;
+        if owner.is_some() {
+            return Err(AppError::Conflict("Email already in use".to_string()));
+        }
+    }
+
+    let mut tx = app_state.db.begin().await?;
+
+    if let Patch::Value(username) = &update.username {
+        sqlx::query(SQL_UPDATE_USER_USERNAME)
+            .bind(user.id)
+            .bind(username)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                if let Some(db_err) = e.as_database_error() {
+                    // 23505: unique_violation
+                    if db_err.code().as_deref() == Some("23505") {
+                        return AppError::Conflict("Username already taken".to_string());
+                    }
+                }
+                e.into()
+            })?;
+    }
+
+    if let Some(bio) = update.bio.as_update() {
+        sqlx::query(SQL_UPDATE_USER_BIO)
+            .bind(user.id)
+            .bind(bio)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let user_row = if update.username == Patch::Missing && update.bio == Patch::Missing {
+        current
+    } else {
+        sqlx::query_as(SQL_ME).bind(user.id).fetch_one(&mut *tx).await?
+    };
+
+    if let Some(email) = &new_email {
+        let token = generate_opaque_token();
+        let expires_at =
+            Utc::now() + chrono::Duration::hours(app_state.auth_config.email_change_expire_hours);
+
+        sqlx::query(SQL_CREATE_EMAIL_CHANGE)
+            .bind(user.id)
+            .bind(email)
+            .bind(hash_opaque_token(&token))
+            .bind(expires_at)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(SQL_CREATE_OUTBOX_MESSAGE)
+            .bind("email_change_verification")
+            .bind(email)
+            .bind("Confirm your new email address")
+            .bind(format!(
+                "Hello {},\n\nUse this token to confirm your new email address: {}\n\nIt expires at {}. If you did not ask for this change, you can ignore this message.",
+                user_row.username, token, expires_at
+            ))
+            .execute(&mut *tx)
+            .await?;
+
+        // The current address hears about it too, in case the account was taken over
+        sqlx::query(SQL_CREATE_OUTBOX_MESSAGE)
+            .bind("email_change_notice")
+            .bind(&user_row.email)
+            .bind("Your email address is being changed")
+            .bind(format!(
+                "Hello {},\n\nA change of your account's email address to {} was requested. It only takes effect once confirmed from that address.",
+                user_row.username, email
+            ))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(MeUpdated {
+        user: User::from(user_row),
+        pending_email: new_email,
+    }))
+}
+
+/// Streams everything stored about the caller, see `export::export_response`.
+pub async fn export_me(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let user_row: UserRow = sqlx::query_as(SQL_ME)
+        .bind(user.id)
+        .fetch_optional(&app_state.db)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
+
+    Ok(export_response(app_state.db.clone(), user_row, query.format))
+}
+
+pub async fn change_password(
+    State(app_state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    user: AuthUser,
+    Json(change): Json<PasswordChange>,
+) -> Result<StatusCode, AppError> {
+    if change.new_password.is_empty() {
+        return Err(AppError::BadRequest("New password must not be empty".to_string()));
+    }
+
+    let current_hash: Option<String> = sqlx::query_scalar(SQL_GET_PASSWORD_HASH)
+        .bind(user.id)
+        .fetch_optional(&app_state.db)
+        .await?;
+
+    let current_hash =
+        current_hash.ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
+
+    // Throttled like logins, or a stolen access token would allow unlimited password guesses
+    let throttle = &app_state.login_throttle;
+    let attempt = throttle.check(&user.id.to_string(), throttle.client_ip(&headers, peer))?;
+    if !verify_password(&app_state.hash_pool, &change.current_password, &current_hash).await? {
+        attempt.record_failure();
+        return Err(AppError::Forbidden("Current password is incorrect".to_string()));
+    }
+    attempt.record_success();
+
+    let password_hash = hash_password(
+        &app_state.hash_pool,
+        &change.new_password,
+        app_state.auth_config.password_policy,
+    )
+    .await?;
+    set_password(&app_state, user.id, &password_hash).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn request_password_reset(
+    State(app_state): State<AppState>,
+    Json(request): Json<PasswordResetRequest>,
+) -> Result<StatusCode, AppError> {
+    let user_row: Option<UserRow> = sqlx::query_as(SQL_GET_USER_BY_EMAIL)
+        .bind(&request.email)
+        .fetch_optional(&app_state.db)
+        .await?;
+
+    // Always answer the same way so the endpoint does not reveal which emails exist
+    let Some(user) = user_row else {
+        return Ok(StatusCode::ACCEPTED);
+    };
+
+    let reset_token = generate_opaque_token();
+    let expires_at = Utc::now()
+        + chrono::Duration::minutes(app_state.auth_config.password_reset_expire_minutes);
+
+    let mut tx = app_state.db.begin().await?;
+
+    sqlx::query(SQL_CREATE_PASSWORD_RESET)
+        .bind(user.id)
+        .bind(hash_opaque_token(&reset_token))
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(SQL_CREATE_OUTBOX_MESSAGE)
+        .bind("password_reset")
+        .bind(&user.email)
+        .bind("Reset your password")
+        .bind(format!(
+            "Hello {},\n\nUse this token to reset your password: {}\n\nIt expires at {}. If you did not ask for a reset, you can ignore this message.",
+            user.username, reset_token, expires_at
+        ))
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub async fn confirm_password_reset(
+    State(app_state): State<AppState>,
+    Json(confirm): Json<PasswordResetConfirm>,
+) -> Result<StatusCode, AppError> {
+    if confirm.new_password.is_empty() {
+        return Err(AppError::BadRequest("New password must not be empty".to_string()));
+    }
+
+    let invalid = || AppError::BadRequest("Invalid or expired reset token".to_string());
+    let token_hash = hash_opaque_token(&confirm.token);
+
+    // Checked before hashing, so made-up tokens cannot keep the hash pool busy
+    let user_id: Option<Uuid> = sqlx::query_scalar(SQL_GET_VALID_PASSWORD_RESET)
+        .bind(&token_hash)
+        .fetch_optional(&app_state.db)
+        .await?;
+    let user_id = user_id.ok_or_else(invalid)?;
+
+    let password_hash = hash_password(
+        &app_state.hash_pool,
+        &confirm.new_password,
+        app_state.auth_config.password_policy,
+    )
+    .await?;
+
+    let mut tx = app_state.db.begin().await?;
+
+    // Consumed with the password change, a concurrent confirmation with the same token fails here
+    let consumed: Option<Uuid> = sqlx::query_scalar(SQL_CONSUME_PASSWORD_RESET)
+        .bind(&token_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if consumed != Some(user_id) {
+        return Err(invalid());
+    }
+    let session_ids = replace_password(&mut tx, user_id, &password_hash).await?;
+    tx.commit().await?;
+
+    revoke_session_tokens(&app_state, user_id, &session_ids).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Applies a pending email change. Password resets sent to the old address stop working.
+pub async fn confirm_email_change(
+    State(app_state): State<AppState>,
+    Json(confirm): Json<EmailChangeConfirm>,
+) -> Result<StatusCode, AppError> {
+    let mut tx = app_state.db.begin().await?;
+
+    let change: Option<EmailChangeRow> = sqlx::query_as(SQL_CONSUME_EMAIL_CHANGE)
+        .bind(hash_opaque_token(&confirm.token))
+        .fetch_optional(&mut *tx)
+        .await?;
+    let change =
+        change.ok_or_else(|| AppError::BadRequest("Invalid or expired token".to_string()))?;
+
+    sqlx::query(SQL_UPDATE_USER_EMAIL)
+        .bind(change.user_id)
+        .bind(&change.new_email)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            if let Some(db_err) = e.as_database_error() {
+                // 23505: unique_violation, the address was taken since the request
+                if db_err.code().as_deref() == Some("23505") {
+                    return AppError::Conflict("Email already in use".to_string());
+                }
+            }
+            e.into()
+        })?;
+
+    sqlx::query(SQL_INVALIDATE_USER_PASSWORD_RESETS)
+        .bind(change.user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stores a new password hash and ends everything that was granted with the old password.
+async fn set_password(app_state: &AppState, user_id: Uuid, password_hash: &str) -> Result<(), AppError> {
+    let mut tx = app_state.db.begin().await?;
+    let session_ids = replace_password(&mut tx, user_id, password_hash).await?;
+    tx.commit().await?;
+
+    revoke_session_tokens(app_state, user_id, &session_ids).await
+}
+
+/// Returns the ended sessions, whose access tokens still need `revoke_session_tokens`
+/// once the transaction is committed.
+async fn replace_password(conn: &mut PgConnection, user_id: Uuid, password_hash: &str) -> Result<Vec<Uuid>, AppError> {
+    sqlx::query(SQL_UPDATE_USER_PASSWORD)
+        .bind(user_id)
+        .bind(password_hash)
+        .execute(&mut *conn)
+        .await?;
+
+    let session_ids = end_user_sessions(conn, user_id).await?;
+
+    sqlx::query(SQL_INVALIDATE_USER_PASSWORD_RESETS)
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(session_ids)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// MFA endpoints
+////////////////////////////////////////////////////////////////////////////////
+
+/// Starts (or restarts) TOTP enrollment. TOTP is only required at login once confirmed.
+pub async fn enroll_totp(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<TotpEnrollment>, AppError> {
+    let user_row: Option<UserRow> = sqlx::query_as(SQL_ME)
+        .bind(user.id)
+        .fetch_optional(&app_state.db)
+        .await?;
+    let user_row = user_row.ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
+
+    let secret = generate_totp_secret();
+    let enrolled: Option<Uuid> = sqlx::query_scalar(SQL_ENROLL_TOTP)
+        .bind(user.id)
+        .bind(&secret)
+        .fetch_optional(&app_state.db)
+        .await?;
+    if enrolled.is_none() {
+        return Err(AppError::Conflict("TOTP is already enabled".to_string()));
+    }
+
+    let otpauth_uri = otpauth_uri(&secret, &app_state.auth_config.totp_issuer, &user_row.email)?;
+    Ok(Json(TotpEnrollment {
+        secret,
+        otpauth_uri,
+    }))
+}
+
+/// Enables TOTP with a first valid code and returns fresh recovery codes.
+pub async fn confirm_totp(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    Json(request): Json<MfaCode>,
+) -> Result<Json<RecoveryCodes>, AppError> {
+    let totp_row: Option<TotpRow> = sqlx::query_as(SQL_GET_TOTP)
+        .bind(user.id)
+        .fetch_optional(&app_state.db)
+        .await?;
+    let totp_row =
+        totp_row.ok_or_else(|| AppError::NotFound("No TOTP enrollment in progress".to_string()))?;
+    if totp_row.confirmed_at.is_some() {
+        return Err(AppError::Conflict("TOTP is already enabled".to_string()));
+    }
+
+    let step = verify_totp(&totp_row.secret, &request.code)?
+        .ok_or_else(|| AppError::BadRequest("Invalid code".to_string()))?;
+
+    let recovery_codes = generate_recovery_codes();
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_opaque_token(&normalize_recovery_code(code)))
+        .collect();
+
+    let mut tx = app_state.db.begin().await?;
+
+    let confirmed = sqlx::query(SQL_CONFIRM_TOTP)
+        .bind(user.id)
+        .bind(step)
+        .execute(&mut *tx)
+        .await?;
+    if confirmed.rows_affected() == 0 {
+        return Err(AppError::Conflict("TOTP is already enabled".to_string()));
+    }
+
+    sqlx::query(SQL_DELETE_RECOVERY_CODES)
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(SQL_CREATE_RECOVERY_CODES)
+        .bind(user.id)
+        .bind(&code_hashes)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+/// Turns TOTP off, which requires a current TOTP code or an unused recovery code.
+pub async fn disable_totp(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    Json(request): Json<MfaCode>,
+) -> Result<StatusCode, AppError> {
+    if !verify_second_factor(&app_state, user.id, &request.code).await? {
+        return Err(AppError::Forbidden("Invalid code".to_string()));
+    }
+
+    let mut tx = app_state.db.begin().await?;
+
+    sqlx::query(SQL_DELETE_TOTP)
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(SQL_DELETE_RECOVERY_CODES)
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Accepts a TOTP code (each time step only once) or an unused recovery code, which is consumed.
+async fn verify_second_factor(app_state: &AppState, user_id: Uuid, code: &str) -> Result<bool, AppError> {
+    if !is_totp_code(code) {
+        let used = sqlx::query(SQL_USE_RECOVERY_CODE)
+            .bind(user_id)
+            .bind(hash_opaque_token(&normalize_recovery_code(code)))
+            .execute(&app_state.db)
+            .await?;
+        return Ok(used.rows_affected() == 1);
+    }
+
+    let totp_row: Option<TotpRow> = sqlx::query_as(SQL_GET_TOTP)
+        .bind(user_id)
+        .fetch_optional(&app_state.db)
+        .await?;
+    let Some(totp_row) = totp_row.filter(|row| row.confirmed_at.is_some()) else {
+        return Ok(false);
+    };
+    let Some(step) = verify_totp(&totp_row.secret, code)? else {
+        return Ok(false);
+    };
+
+    let used = sqlx::query(SQL_USE_TOTP_STEP)
+        .bind(user_id)
+        .bind(step)
+        .execute(&app_state.db)
+        .await?;
+    Ok(used.rows_affected() == 1)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// OIDC endpoints
+////////////////////////////////////////////////////////////////////////////////
+
+/// How long the IdP login page may take before the callback is refused
+const OIDC_STATE_EXPIRE_MINUTES: i64 = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+fn oidc_client(app_state: &AppState) -> Result<&OidcClient, AppError> {
+    app_state
+        .oidc
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("OIDC login is not configured".to_string()))
+}
+
+/// Redirects to the IdP. `?mode=cookie` makes the callback answer with session cookies.
+pub async fn oidc_start(
+    State(app_state): State<AppState>,
+    jar: CookieJar,
+    Query(query): Query<TokenDeliveryQuery>,
+) -> Result<(CookieJar, Redirect), AppError> {
+    let oidc = oidc_client(&app_state)?;
+
+    let state = generate_opaque_token();
+    let nonce = generate_opaque_token();
+    let code_verifier = generate_opaque_token();
+    let authorization_url = oidc.authorization_url(&state, &nonce, &code_verifier).await?;
+
+    sqlx::query(SQL_CREATE_OIDC_STATE)
+        .bind(hash_opaque_token(&state))
+        .bind(&code_verifier)
+        .bind(&nonce)
+        .bind(matches!(query.mode, TokenDelivery::Cookie))
+        .bind(Utc::now() + chrono::Duration::minutes(OIDC_STATE_EXPIRE_MINUTES))
+        .execute(&app_state.db)
+        .await?;
+
+    let jar = set_oidc_state_cookie(
+        jar,
+        state,
+        &app_state.auth_config.cookies,
+        time::Duration::minutes(OIDC_STATE_EXPIRE_MINUTES),
+    );
+    Ok((jar, Redirect::to(&authorization_url)))
+}
+
+pub async fn oidc_callback(
+    State(app_state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<Response, AppError> {
+    let oidc = oidc_client(&app_state)?;
+
+    if let Some(error) = query.error {
+        return Err(AppError::Unauthorized(format!("Identity provider returned {}", error)));
+    }
+    let (Some(code), Some(state)) = (query.code, query.state) else {
+        return Err(AppError::BadRequest("Missing code or state".to_string()));
+    };
+    verify_oidc_state(&state, &jar)?;
+
+    // Single use: the state row is deleted as it is read
+    let pending: Option<OidcStateRow> = sqlx::query_as(SQL_CONSUME_OIDC_STATE)
+        .bind(hash_opaque_token(&state))
+        .fetch_optional(&app_state.db)
+        .await?;
+    let pending =
+        pending.ok_or_else(|| AppError::BadRequest("Invalid or expired state".to_string()))?;
+
+    let identity = oidc
+        .exchange_code(&code, &pending.code_verifier, &pending.nonce)
+        .await?;
+    let user_id = find_or_create_oidc_user(&app_state, oidc.issuer(), &identity).await?;
+
+    let permissions: Vec<String> = sqlx::query_scalar(SQL_GET_USER_PERMISSIONS)
+        .bind(user_id)
+        .fetch_one(&app_state.db)
+        .await?;
+    let tokens = start_session(&app_state, user_id, &permissions, &headers, peer).await?;
+
+    let delivery = if pending.cookie_mode {
+        TokenDelivery::Cookie
+    } else {
+        TokenDelivery::Bearer
+    };
+    let response = token_response(delivery, jar.clone(), tokens, &app_state.auth_config);
+    Ok((clear_oidc_state_cookie(jar), response).into_response())
+}
+
+/// The linked account if any, else the account with the same verified email, else a new account.
+async fn find_or_create_oidc_user(
+    app_state: &AppState,
+    issuer: &str,
+    identity: &IdTokenClaims,
+) -> Result<Uuid, AppError> {
+    let linked_id: Option<Uuid> = sqlx::query_scalar(SQL_GET_OIDC_IDENTITY)
+        .bind(issuer)
+        .bind(&identity.sub)
+        .fetch_optional(&app_state.db)
+        .await?;
+    if let Some(user_id) = linked_id {
+        return Ok(user_id);
+    }
+
+    let Some(email) = identity.email.as_deref().filter(|_| identity.email_verified) else {
+        return Err(AppError::Forbidden(
+            "Identity provider did not return a verified email".to_string(),
+        ));
+    };
+
+    let existing: Option<UserRow> = sqlx::query_as(SQL_GET_USER_BY_EMAIL)
+        .bind(email)
+        .fetch_optional(&app_state.db)
+        .await?;
+    let user_id = match existing {
+        Some(user) => user.id,
+        None => create_oidc_user(app_state, identity, email).await?,
+    };
+
+    sqlx::query(SQL_LINK_OIDC_IDENTITY)
+        .bind(issuer)
+        .bind(&identity.sub)
+        .bind(user_id)
+        .bind(email)
+        .execute(&app_state.db)
+        .await?;
+
+    Ok(user_id)
+}
+
+async fn create_oidc_user(
+    app_state: &AppState,
+    identity: &IdTokenClaims,
+    email: &str,
+) -> Result<Uuid, AppError> {
+    // Nobody knows this password: the account can only sign in through the IdP until a reset
+    let password_hash = hash_password(
+        &app_state.hash_pool,
+        &generate_opaque_token(),
+        app_state.auth_config.password_policy,
+    )
+    .await?;
+
+    let base_username = identity
+        .preferred_username
+        .clone()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or(email).to_string());
+    // On a username collision, retry once with a random suffix
+    let candidates = [
+        base_username.clone(),
+        format!("{}-{}", base_username, &Uuid::new_v4().simple().to_string()[..6]),
+    ];
+
+    for username in candidates {
+        let created = sqlx::query_scalar(SQL_CREATE_USER)
+            .bind(&username)
+            .bind(email)
+            .bind(&password_hash)
+            .bind(None::<String>)
+            .fetch_one(&app_state.db)
+            .await;
+
+        match created {
+            Ok(user_id) => return Ok(user_id),
+            Err(e) if e.as_database_error().and_then(|db_err| db_err.code()).as_deref() == Some("23505") => {
+                continue
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(AppError::Conflict("Username or email already exists".to_string()))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// API keys endpoints
+////////////////////////////////////////////////////////////////////////////////
+
+pub async fn create_api_key(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    Json(key_data): Json<ApiKeyCreate>,
+) -> Result<(StatusCode, Json<ApiKeyCreated>), AppError> {
+    // Otherwise an expiring or soon revoked key could mint a replacement for itself
+    if user.claims.api_key_id.is_some() {
+        return Err(AppError::Forbidden("API keys cannot create API keys".to_string()));
+    }
+    if key_data.name.trim().is_empty() || key_data.name.len() > 100 {
+        return Err(AppError::BadRequest("Name must be 1 to 100 characters".to_string()));
+    }
+    if key_data.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(AppError::BadRequest("Expiration must be in the future".to_string()));
+    }
+    // A key can only carry permissions the caller holds right now
+    if let Some(scope) = key_data.scopes.iter().find(|scope| !user.claims.has_scope(scope)) {
+        return Err(AppError::Forbidden(format!("Cannot grant scope: {}", scope)));
+    }
+
+    let key = generate_api_key();
+    let key_row: ApiKeyRow = sqlx::query_as(SQL_CREATE_API_KEY)
+        .bind(user.id)
+        .bind(key_data.name.trim())
+        .bind(&key[..API_KEY_PREFIX.len() + 8])
+        .bind(hash_opaque_token(&key))
+        .bind(&key_data.scopes)
+        .bind(key_data.expires_at)
+        .fetch_one(&app_state.db)
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ApiKeyCreated {
+            api_key: ApiKey::from(key_row),
+            key,
+        }),
+    ))
+}
+
+pub async fn list_api_keys(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<ApiKey>>, AppError> {
+    let key_rows: Vec<ApiKeyRow> = sqlx::query_as(SQL_LIST_API_KEYS)
+        .bind(user.id)
+        .fetch_all(&app_state.db)
+        .await?;
+
+    Ok(Json(key_rows.into_iter().map(ApiKey::from).collect()))
+}
+
+pub async fn revoke_api_key(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    Path(key_id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let key_uuid = Uuid::parse_str(&key_id)
+        .map_err(|_| AppError::BadRequest("Invalid API key ID".to_string()))?;
+
+    let result = sqlx::query(SQL_REVOKE_API_KEY)
+        .bind(key_uuid)
+        .bind(user.id)
+        .execute(&app_state.db)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("API key not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Sessions endpoints
+////////////////////////////////////////////////////////////////////////////////
+
+pub async fn list_sessions(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<Session>>, AppError> {
+    let session_rows: Vec<SessionRow> = sqlx::query_as(SQL_LIST_SESSIONS)
+        .bind(user.id)
+        .fetch_all(&app_state.db)
+        .await?;
+
+    let sessions = session_rows
+        .into_iter()
+        .map(|row| Session {
+            id: row.id.to_string(),
+            user_agent: row.user_agent,
+            ip_address: row.ip_address,
+            created_at: row.created_at,
+            last_seen_at: row.last_seen_at,
+            current: user.claims.sid == Some(row.id),
+        })
+        .collect();
+
+    Ok(Json(sessions))
+}
+
+pub async fn revoke_session(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    Path(session_id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let session_uuid = Uuid::parse_str(&session_id)
+        .map_err(|_| AppError::BadRequest("Invalid session ID".to_string()))?;
+
+    if !end_session(&app_state, user.id, session_uuid).await? {
+        return Err(AppError::NotFound("Session not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Marks the session revoked, ends its refresh tokens and denylists its access tokens.
+/// Returns false when the user has no such active session.
+async fn end_session(app_state: &AppState, user_id: Uuid, session_id: Uuid) -> Result<bool, AppError> {
+    let mut tx = app_state.db.begin().await?;
+
+    let revoked: Option<Uuid> = sqlx::query_scalar(SQL_REVOKE_SESSION)
+        .bind(session_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if revoked.is_none() {
+        return Ok(false);
+    }
+
+    sqlx::query(SQL_REVOKE_REFRESH_TOKEN_FAMILY)
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    revoke_session_tokens(app_state, user_id, &[session_id]).await?;
+    Ok(true)
+}
+
 ////////////////////////////////////////////////////////////////////////////////
-// Auth endpoints
+// Metrics endpoints
 ////////////////////////////////////////////////////////////////////////////////
 
-pub async fn login(
+pub async fn hash_pool_metrics(State(app_state): State<AppState>) -> Json<HashPoolStats> {
+    Json(app_state.hash_pool.stats())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Profile endpoints (public)
+////////////////////////////////////////////////////////////////////////////////
+
+pub async fn get_user_profile(
     State(app_state): State<AppState>,
-    Json(credentials): Json<LoginCredentials>,
-) -> Result<Json<LoginResponse>, AppError> {
-    let login_row: Option<LoginRow> = sqlx::query_as(SQL_LOGIN)
-        .bind(&credentials.email)
+    Path(target_user_id): Path<String>,
+) -> Result<Json<UserProfile>, AppError> {
+    let target_uuid = Uuid::parse_str(&target_user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+
+    let profile_row: Option<UserProfileRow> = sqlx::query_as(SQL_GET_USER_PROFILE)
+        .bind(target_uuid)
         .fetch_optional(&app_state.db)
         .await?;
 
-    if let Some(row) = login_row {
-        let is_valid = verify_password(&credentials.password, &row.password_hash).await?;
-
-        if is_valid {
-            let token = create_token(&row.id, row.is_admin, &app_state.auth_config)?;
-            return Ok(Json(LoginResponse {
-                access_token: token,
-            }));
-        }
+    match profile_row {
+        Some(row) => Ok(Json(UserProfile::from(row))),
+        None => Err(AppError::NotFound("User not found".to_string())),
     }
-
-    Err(AppError::Unauthorized("Invalid credentials".to_string()))
 }
 
-pub async fn me(
+pub async fn get_user_profile_by_username(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
-) -> Result<Json<User>, AppError> {
-    let user_uuid = Uuid::parse_str(&claims.sub)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
-
-    let user_row: Option<UserRow> = sqlx::query_as(SQL_ME)
-        .bind(user_uuid)
+    Path(username): Path<String>,
+) -> Result<Json<UserProfile>, AppError> {
+    let profile_row: Option<UserProfileRow> = sqlx::query_as(SQL_GET_USER_PROFILE_BY_USERNAME)
+        .bind(&username)
         .fetch_optional(&app_state.db)
         .await?;
 
-    match user_row {
-        Some(row) => Ok(Json(User::from(row))),
-        None => Err(AppError::Unauthorized("User not found".to_string())),
+    match profile_row {
+        Some(row) => Ok(Json(UserProfile::from(row))),
+        None => Err(AppError::NotFound("User not found".to_string())),
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
-// Users endpoints (Admin only)
+// Users endpoints (guarded by users:read / users:write in main.rs)
 ////////////////////////////////////////////////////////////////////////////////
 
 pub async fn create_user(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
+    _admin: AdminUser,
     Json(user_data): Json<CreateUser>,
 ) -> Result<(StatusCode, Json<User>), AppError> {
-    if !claims.is_admin {
-        return Err(AppError::Forbidden("Admin access required".to_string()));
-    }
-
-    let password_hash = hash_password(&user_data.password).await?;
+    let password_hash = hash_password(
+        &app_state.hash_pool,
+        &user_data.password,
+        app_state.auth_config.password_policy,
+    )
+    .await?;
 
     let created_id: Uuid = sqlx::query_scalar(SQL_CREATE_USER)
         .bind(&user_data.username)
@@ -103,20 +1495,86 @@ pub async fn create_user(
     Ok((StatusCode::CREATED, Json(User::from(user_row))))
 }
 
+/// Bulk `POST /users` from a CSV (`text/csv`) or NDJSON (`application/x-ndjson`) body.
+pub async fn import_users(
+    State(app_state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ImportReport>, AppError> {
+    let format = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(ImportFormat::from_content_type)
+        .ok_or_else(|| {
+            AppError::BadRequest("Content-Type must be text/csv or application/x-ndjson".to_string())
+        })?;
+
+    let report = import::import_users(
+        &app_state.db,
+        &app_state.hash_pool,
+        app_state.auth_config.password_policy,
+        format,
+        &body,
+    )
+    .await?;
+
+    tracing::info!(
+        "Imported {} users ({} failed) by {}",
+        report.imported,
+        report.failed,
+        admin.id
+    );
+    Ok(Json(report))
+}
+
+/// Filters of `GET /users`, all optional
+#[derive(Debug, Deserialize)]
+pub struct UserSearchQuery {
+    /// Case-insensitive partial match on username or email, best matches first
+    pub q: Option<String>,
+    #[serde(rename = "isAdmin")]
+    pub is_admin: Option<bool>,
+    #[serde(rename = "createdAfter")]
+    pub created_after: Option<DateTime<Utc>>,
+    #[serde(rename = "createdBefore")]
+    pub created_before: Option<DateTime<Utc>>,
+}
+
 pub async fn list_users(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
     Query(pagination): Query<PaginationQuery>,
+    Query(search): Query<UserSearchQuery>,
 ) -> Result<Json<Vec<User>>, AppError> {
-    if !claims.is_admin {
-        return Err(AppError::Forbidden("Admin access required".to_string()));
-    }
-
-    let user_rows: Vec<UserRow> = sqlx::query_as(SQL_LIST_USERS)
-        .bind(pagination.limit)
-        .bind(pagination.offset)
-        .fetch_all(&app_state.db)
-        .await?;
+    let q = search.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+
+    let user_rows: Vec<UserRow> = if q.is_none()
+        && search.is_admin.is_none()
+        && search.created_after.is_none()
+        && search.created_before.is_none()
+    {
+        sqlx::query_as(SQL_LIST_USERS)
+            .bind(pagination.limit)
+            .bind(pagination.offset)
+            .fetch_all(&app_state.db)
+            .await?
+    } else {
+        // `%` and `_` typed by the admin are matched literally
+        let pattern = q.map(|q| {
+            let escaped = q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            format!("%{}%", escaped)
+        });
+        sqlx::query_as(SQL_SEARCH_USERS)
+            .bind(q)
+            .bind(pattern)
+            .bind(search.is_admin)
+            .bind(search.created_after)
+            .bind(search.created_before)
+            .bind(pagination.limit)
+            .bind(pagination.offset)
+            .fetch_all(&app_state.db)
+            .await?
+    };
 
     let users: Vec<User> = user_rows.into_iter().map(User::from).collect();
     Ok(Json(users))
@@ -124,13 +1582,8 @@ pub async fn list_users(
 
 pub async fn get_user(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
     Path(target_user_id): Path<String>,
 ) -> Result<Json<User>, AppError> {
-    if !claims.is_admin {
-        return Err(AppError::Forbidden("Admin access required".to_string()));
-    }
-
     let target_uuid = Uuid::parse_str(&target_user_id)
         .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
 
@@ -145,16 +1598,33 @@ pub async fn get_user(
     }
 }
 
+/// Data export on behalf of a user, for data-subject requests sent to the admins.
+pub async fn export_user(
+    State(app_state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    Path(target_user_id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let target_uuid = Uuid::parse_str(&target_user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+
+    // Deleted users are included: the grace period is when their data is most often asked for
+    let user_row: UserRow = sqlx::query_as(SQL_GET_USER_INCLUDING_DELETED)
+        .bind(target_uuid)
+        .fetch_optional(&app_state.db)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    tracing::info!("User {} exported by {}", target_uuid, admin.id);
+    Ok(export_response(app_state.db.clone(), user_row, query.format))
+}
+
 pub async fn update_user(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
+    _admin: AdminUser,
     Path(target_user_id): Path<String>,
     Json(update_data): Json<UpdateUser>,
 ) -> Result<Json<User>, AppError> {
-    if !claims.is_admin {
-        return Err(AppError::Forbidden("Admin access required".to_string()));
-    }
-
     let target_uuid = Uuid::parse_str(&target_user_id)
         .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
 
@@ -170,44 +1640,343 @@ pub async fn update_user(
     }
 }
 
-pub async fn delete_user(
+/// Partial update: each field present in the body is written with its own statement,
+/// all in one transaction, and absent fields are not touched.
+pub async fn patch_user(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
+    AdminUser(admin): AdminUser,
     Path(target_user_id): Path<String>,
-) -> Result<StatusCode, AppError> {
-    if !claims.is_admin {
-        return Err(AppError::Forbidden("Admin access required".to_string()));
+    Json(patch): Json<UserPatch>,
+) -> Result<Json<User>, AppError> {
+    let target_uuid = Uuid::parse_str(&target_user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+
+    for (field, is_null) in [
+        ("username", patch.username == Patch::Null),
+        ("email", patch.email == Patch::Null),
+        ("isAdmin", patch.is_admin == Patch::Null),
+        ("password", patch.password == Patch::Null),
+    ] {
+        if is_null {
+            return Err(AppError::BadRequest(format!("{} cannot be null", field)));
+        }
+    }
+    if let Patch::Value(username) = &patch.username {
+        if username.trim().is_empty() || username.len() > 255 {
+            return Err(AppError::BadRequest("Username must be 1 to 255 characters".to_string()));
+        }
+    }
+    if let Patch::Value(email) = &patch.email {
+        if email.len() > 255 || !email.contains('@') {
+            return Err(AppError::BadRequest("Invalid email".to_string()));
+        }
+    }
+    if patch.is_admin == Patch::Value(false) && target_uuid == admin.id {
+        return Err(AppError::Forbidden("Cannot remove your own admin role".to_string()));
+    }
+
+    // Hashed before the transaction starts, it can wait for the hash pool
+    let password_hash = match &patch.password {
+        Patch::Value(password) if password.is_empty() => {
+            return Err(AppError::BadRequest("Password must not be empty".to_string()));
+        }
+        Patch::Value(password) => Some(
+            hash_password(&app_state.hash_pool, password, app_state.auth_config.password_policy)
+                .await?,
+        ),
+        _ => None,
+    };
+
+    let conflict = |message: &'static str| {
+        move |e: sqlx::Error| -> AppError {
+            if let Some(db_err) = e.as_database_error() {
+                // 23505: unique_violation
+                if db_err.code().as_deref() == Some("23505") {
+                    return AppError::Conflict(message.to_string());
+                }
+            }
+            e.into()
+        }
+    };
+
+    let mut tx = app_state.db.begin().await?;
+
+    let exists: Option<UserRow> = sqlx::query_as(SQL_GET_USER)
+        .bind(target_uuid)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if exists.is_none() {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    if let Patch::Value(username) = &patch.username {
+        sqlx::query(SQL_UPDATE_USER_USERNAME)
+            .bind(target_uuid)
+            .bind(username)
+            .execute(&mut *tx)
+            .await
+            .map_err(conflict("Username already taken"))?;
+    }
+
+    if let Patch::Value(email) = &patch.email {
+        sqlx::query(SQL_UPDATE_USER_EMAIL)
+            .bind(target_uuid)
+            .bind(email)
+            .execute(&mut *tx)
+            .await
+            .map_err(conflict("Email already in use"))?;
+        // Reset tokens were sent to the previous address
+        sqlx::query(SQL_INVALIDATE_USER_PASSWORD_RESETS)
+            .bind(target_uuid)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    if let Some(bio) = patch.bio.as_update() {
+        sqlx::query(SQL_UPDATE_USER_BIO)
+            .bind(target_uuid)
+            .bind(bio)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // users.is_admin follows the admin role through the user_roles trigger
+    match patch.is_admin {
+        Patch::Value(true) => {
+            sqlx::query(SQL_ASSIGN_USER_ROLES)
+                .bind(target_uuid)
+                .bind(["admin"])
+                .execute(&mut *tx)
+                .await?;
+        }
+        Patch::Value(false) => {
+            sqlx::query(SQL_REMOVE_USER_ROLE)
+                .bind(target_uuid)
+                .bind("admin")
+                .execute(&mut *tx)
+                .await?;
+        }
+        _ => {}
     }
 
+    let session_ids = match &password_hash {
+        Some(password_hash) => replace_password(&mut tx, target_uuid, password_hash).await?,
+        None => Vec::new(),
+    };
+
+    let user_row: UserRow = sqlx::query_as(SQL_GET_USER)
+        .bind(target_uuid)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    revoke_session_tokens(&app_state, target_uuid, &session_ids).await?;
+
+    tracing::info!("User {} updated by {}", target_uuid, admin.id);
+    Ok(Json(User::from(user_row)))
+}
+
+pub async fn delete_user(
+    State(app_state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    Path(target_user_id): Path<String>,
+) -> Result<StatusCode, AppError> {
     let target_uuid = Uuid::parse_str(&target_user_id)
         .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
 
-    let result = sqlx::query(SQL_DELETE_USER)
+    // Soft delete: the user disappears and is signed out, their content stays until the purge
+    let mut tx = app_state.db.begin().await?;
+
+    let result = sqlx::query(SQL_SOFT_DELETE_USER)
         .bind(target_uuid)
-        .execute(&app_state.db)
+        .execute(&mut *tx)
         .await?;
 
     if result.rows_affected() == 0 {
         return Err(AppError::NotFound("User not found".to_string()));
     }
 
+    let session_ids = end_user_sessions(&mut tx, target_uuid).await?;
+
+    tx.commit().await?;
+
+    revoke_session_tokens(&app_state, target_uuid, &session_ids).await?;
+
+    tracing::info!("User {} deleted by {}", target_uuid, admin.id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Undoes a delete during the grace period. Sessions ended by the delete stay ended.
+pub async fn restore_user(
+    State(app_state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    Path(target_user_id): Path<String>,
+) -> Result<Json<User>, AppError> {
+    let target_uuid = Uuid::parse_str(&target_user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+
+    let user_row: Option<UserRow> = sqlx::query_as(SQL_RESTORE_USER)
+        .bind(target_uuid)
+        .bind(app_state.auth_config.user_delete_grace_days)
+        .fetch_optional(&app_state.db)
+        .await?;
+
+    let user_row = user_row.ok_or_else(|| {
+        AppError::NotFound("No deleted user to restore within the grace period".to_string())
+    })?;
+
+    tracing::info!("User {} restored by {}", target_uuid, admin.id);
+    Ok(Json(User::from(user_row)))
+}
+
+pub async fn list_roles(State(app_state): State<AppState>) -> Result<Json<Vec<Role>>, AppError> {
+    let role_rows: Vec<RoleRow> = sqlx::query_as(SQL_LIST_ROLES)
+        .fetch_all(&app_state.db)
+        .await?;
+
+    Ok(Json(role_rows.into_iter().map(Role::from).collect()))
+}
+
+/// Replaces the roles of a user. Tokens already issued keep their scope until they are refreshed.
+pub async fn set_user_roles(
+    State(app_state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    Path(target_user_id): Path<String>,
+    Json(roles_data): Json<UserRolesUpdate>,
+) -> Result<Json<UserRoles>, AppError> {
+    let target_uuid = Uuid::parse_str(&target_user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+
+    // Same rule as `PATCH /users/{userId}` with `"isAdmin": false`
+    if target_uuid == admin.id && !roles_data.roles.iter().any(|role| role == "admin") {
+        return Err(AppError::Forbidden("Cannot remove your own admin role".to_string()));
+    }
+
+    let mut tx = app_state.db.begin().await?;
+
+    sqlx::query(SQL_CLEAR_USER_ROLES)
+        .bind(target_uuid)
+        .execute(&mut *tx)
+        .await?;
+
+    let mut roles: Vec<String> = sqlx::query_scalar(SQL_ASSIGN_USER_ROLES)
+        .bind(target_uuid)
+        .bind(&roles_data.roles)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| {
+            if let Some(db_err) = e.as_database_error() {
+                // 23503: foreign_key_violation, either on the user or on one of the roles
+                if db_err.code().as_deref() == Some("23503") {
+                    return match db_err.constraint() {
+                        Some("user_roles_user_id_fkey") => AppError::NotFound("User not found".to_string()),
+                        _ => AppError::BadRequest("Unknown role".to_string()),
+                    };
+                }
+            }
+            e.into()
+        })?;
+
+    tx.commit().await?;
+
+    roles.sort();
+    tracing::info!("Roles of user {} set to {:?} by {}", target_uuid, roles, admin.id);
+    Ok(Json(UserRoles {
+        user_id: target_uuid.to_string(),
+        roles,
+    }))
+}
+
+/// Force-logout: every session of the user ends and their access tokens stop working.
+pub async fn revoke_user_sessions(
+    State(app_state): State<AppState>,
+    AdminUser(admin): AdminUser,
+    Path(target_user_id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let target_uuid = Uuid::parse_str(&target_user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+
+    let mut tx = app_state.db.begin().await?;
+    let session_ids = end_user_sessions(&mut tx, target_uuid).await?;
+    tx.commit().await?;
+
+    revoke_session_tokens(&app_state, target_uuid, &session_ids).await?;
+
+    tracing::info!(
+        "{} sessions of user {} revoked by {}",
+        session_ids.len(),
+        target_uuid,
+        admin.id
+    );
     Ok(StatusCode::NO_CONTENT)
 }
 
+pub async fn impersonate_user(
+    State(app_state): State<AppState>,
+    admin: AuthUser,
+    method: Method,
+    uri: Uri,
+    Path(target_user_id): Path<String>,
+) -> Result<Json<ImpersonationToken>, AppError> {
+    let target_uuid = Uuid::parse_str(&target_user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+    if target_uuid == admin.id {
+        return Err(AppError::BadRequest("Cannot impersonate yourself".to_string()));
+    }
+
+    let target: Option<UserRow> = sqlx::query_as(SQL_GET_USER)
+        .bind(target_uuid)
+        .fetch_optional(&app_state.db)
+        .await?;
+    if target.is_none() {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    let permissions: Vec<String> = sqlx::query_scalar(SQL_GET_USER_PERMISSIONS)
+        .bind(target_uuid)
+        .fetch_one(&app_state.db)
+        .await?;
+    // Admins answer for their own actions, and impersonation tokens cannot be chained
+    if permissions.iter().any(|p| p == Permission::UsersImpersonate.as_str()) {
+        return Err(AppError::Forbidden("Cannot impersonate another administrator".to_string()));
+    }
+
+    let config = &app_state.auth_config;
+    let access_token = create_impersonation_token(&target_uuid, &permissions, &admin.id, config)?;
+    let expires_at = Utc::now() + chrono::Duration::minutes(config.impersonation_expire_minutes);
+
+    audit::record(
+        &app_state.db,
+        AuditEntry {
+            actor_id: admin.id,
+            user_id: target_uuid,
+            method: method.to_string(),
+            path: uri.path().to_string(),
+        },
+        StatusCode::OK,
+    )
+    .await;
+    tracing::info!("User {} impersonated by {}", target_uuid, admin.id);
+
+    Ok(Json(ImpersonationToken {
+        access_token,
+        user_id: target_uuid.to_string(),
+        expires_at,
+    }))
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // Posts endpoints
 ////////////////////////////////////////////////////////////////////////////////
 
 pub async fn create_post(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
+    user: AuthUser,
     Json(post_data): Json<PostCreate>,
 ) -> Result<(StatusCode, Json<Post>), AppError> {
-    let user_uuid = Uuid::parse_str(&claims.sub)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
-
     let post_row: PostCreateRow = sqlx::query_as(SQL_CREATE_POST)
-        .bind(user_uuid)
+        .bind(user.id)
         .bind(&post_data.content)
         .fetch_one(&app_state.db)
         .await
@@ -263,11 +2032,9 @@ pub async fn get_post(
 
 pub async fn delete_post(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
+    user: AuthUser,
     Path(post_id): Path<String>,
 ) -> Result<StatusCode, AppError> {
-    let user_uuid = Uuid::parse_str(&claims.sub)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
     let post_uuid = Uuid::parse_str(&post_id)
         .map_err(|_| AppError::BadRequest("Invalid post ID".to_string()))?;
 
@@ -279,7 +2046,7 @@ pub async fn delete_post(
 
     let author_id = author_id.ok_or_else(|| AppError::NotFound("Post not found".to_string()))?;
 
-    if author_id != user_uuid && !claims.is_admin {
+    if author_id != user.id && !user.has_permission(Permission::PostsDeleteAny) {
         return Err(AppError::Forbidden(
             "You can only delete your own posts".to_string(),
         ));
@@ -299,17 +2066,15 @@ pub async fn delete_post(
 
 pub async fn create_comment(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
+    user: AuthUser,
     Path(post_id): Path<String>,
     Json(comment_data): Json<CommentCreate>,
 ) -> Result<(StatusCode, Json<Comment>), AppError> {
-    let user_uuid = Uuid::parse_str(&claims.sub)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
     let post_uuid = Uuid::parse_str(&post_id)
         .map_err(|_| AppError::BadRequest("Invalid post ID".to_string()))?;
 
     let comment_row: CommentRow = sqlx::query_as(SQL_CREATE_COMMENT)
-        .bind(user_uuid)
+        .bind(user.id)
         .bind(post_uuid)
         .bind(&comment_data.content)
         .fetch_one(&app_state.db)
@@ -361,16 +2126,14 @@ pub async fn list_comments(
 
 pub async fn like_post(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
+    user: AuthUser,
     Path(post_id): Path<String>,
 ) -> Result<StatusCode, AppError> {
-    let user_uuid = Uuid::parse_str(&claims.sub)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
     let post_uuid = Uuid::parse_str(&post_id)
         .map_err(|_| AppError::BadRequest("Invalid post ID".to_string()))?;
 
     let result = sqlx::query(SQL_CREATE_LIKE)
-        .bind(user_uuid)
+        .bind(user.id)
         .bind(post_uuid)
         .execute(&app_state.db)
         .await;
@@ -394,16 +2157,14 @@ pub async fn like_post(
 
 pub async fn unlike_post(
     State(app_state): State<AppState>,
-    Extension(claims): Extension<Claims>,
+    user: AuthUser,
     Path(post_id): Path<String>,
 ) -> Result<StatusCode, AppError> {
-    let user_uuid = Uuid::parse_str(&claims.sub)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
     let post_uuid = Uuid::parse_str(&post_id)
         .map_err(|_| AppError::BadRequest("Invalid post ID".to_string()))?;
 
     let result = sqlx::query(SQL_DELETE_LIKE)
-        .bind(user_uuid)
+        .bind(user.id)
         .bind(post_uuid)
         .execute(&app_state.db)
         .await?;
@@ -414,3 +2175,104 @@ pub async fn unlike_post(
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+////////////////////////////////////////////////////////////////////////////////
+// Follows endpoints
+////////////////////////////////////////////////////////////////////////////////
+
+/// Following is idempotent: following the same user again is not an error.
+pub async fn follow_user(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    Path(target_user_id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let target_uuid = Uuid::parse_str(&target_user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+
+    if target_uuid == user.id {
+        return Err(AppError::BadRequest("You cannot follow yourself".to_string()));
+    }
+
+    // Deleted users keep their row until the purge, so the foreign key alone would accept them
+    let target: Option<UserRow> = sqlx::query_as(SQL_GET_USER)
+        .bind(target_uuid)
+        .fetch_optional(&app_state.db)
+        .await?;
+    if target.is_none() {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    sqlx::query(SQL_CREATE_FOLLOW)
+        .bind(user.id)
+        .bind(target_uuid)
+        .execute(&app_state.db)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unfollow_user(
+    State(app_state): State<AppState>,
+    user: AuthUser,
+    Path(target_user_id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let target_uuid = Uuid::parse_str(&target_user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+
+    let result = sqlx::query(SQL_DELETE_FOLLOW)
+        .bind(user.id)
+        .bind(target_uuid)
+        .execute(&app_state.db)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("User or follow not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_followers(
+    State(app_state): State<AppState>,
+    Path(target_user_id): Path<String>,
+    Query(pagination): Query<PaginationQuery>,
+) -> Result<Json<Vec<FollowUser>>, AppError> {
+    list_follows(&app_state, &target_user_id, SQL_LIST_FOLLOWERS, pagination).await
+}
+
+pub async fn list_following(
+    State(app_state): State<AppState>,
+    Path(target_user_id): Path<String>,
+    Query(pagination): Query<PaginationQuery>,
+) -> Result<Json<Vec<FollowUser>>, AppError> {
+    list_follows(&app_state, &target_user_id, SQL_LIST_FOLLOWING, pagination).await
+}
+
+/// Newest follows first. Deleted users are left out of the list, but still counted in the
+/// profile until they are purged.
+async fn list_follows(
+    app_state: &AppState,
+    target_user_id: &str,
+    sql: &'static str,
+    pagination: PaginationQuery,
+) -> Result<Json<Vec<FollowUser>>, AppError> {
+    let target_uuid = Uuid::parse_str(target_user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+
+    let target: Option<UserRow> = sqlx::query_as(SQL_GET_USER)
+        .bind(target_uuid)
+        .fetch_optional(&app_state.db)
+        .await?;
+    if target.is_none() {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    let follow_rows: Vec<FollowUserRow> = sqlx::query_as(sql)
+        .bind(target_uuid)
+        .bind(pagination.limit)
+        .bind(pagination.offset)
+        .fetch_all(&app_state.db)
+        .await?;
+
+    Ok(Json(follow_rows.into_iter().map(FollowUser::from).collect()))
+}
@@ -0,0 +1,212 @@
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc::{sync_channel, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+
+use crate::{auth::env_or, error::AppError};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct QueuedJob {
+    job: Job,
+    enqueued_at: Instant,
+}
+
+/// Upper bounds (in milliseconds) of the queue wait-time histogram buckets.
+const WAIT_BUCKETS_MS: [u64; 9] = [1, 5, 10, 25, 50, 100, 250, 500, 1000];
+
+/// Dedicated thread pool for password hashing and verification.
+///
+/// Hashing is deliberately slow, so it gets its own fixed set of threads instead of
+/// Tokio's shared blocking pool. Jobs wait in a bounded queue; once it is full new
+/// jobs are rejected with `503` right away rather than piling up latency.
+pub struct HashPool {
+    sender: SyncSender<QueuedJob>,
+    workers: usize,
+    queue_capacity: usize,
+    metrics: Arc<HashPoolMetrics>,
+}
+
+#[derive(Default)]
+struct HashPoolMetrics {
+    queue_depth: AtomicUsize,
+    busy_workers: AtomicUsize,
+    completed: AtomicU64,
+    rejected: AtomicU64,
+    wait_count: AtomicU64,
+    wait_sum_us: AtomicU64,
+    wait_max_us: AtomicU64,
+    wait_buckets: [AtomicU64; WAIT_BUCKETS_MS.len() + 1],
+}
+
+impl HashPoolMetrics {
+    fn record_wait(&self, wait: Duration) {
+        let wait_us = wait.as_micros() as u64;
+        self.wait_count.fetch_add(1, Ordering::Relaxed);
+        self.wait_sum_us.fetch_add(wait_us, Ordering::Relaxed);
+        self.wait_max_us.fetch_max(wait_us, Ordering::Relaxed);
+
+        let bucket = WAIT_BUCKETS_MS
+            .iter()
+            .position(|bound_ms| wait_us <= bound_ms * 1000)
+            .unwrap_or(WAIT_BUCKETS_MS.len());
+        self.wait_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HashPoolStats {
+    pub workers: usize,
+    #[serde(rename = "queueCapacity")]
+    pub queue_capacity: usize,
+    #[serde(rename = "queueDepth")]
+    pub queue_depth: usize,
+    #[serde(rename = "busyWorkers")]
+    pub busy_workers: usize,
+    pub completed: u64,
+    pub rejected: u64,
+    #[serde(rename = "waitTime")]
+    pub wait_time: WaitTimeStats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WaitTimeStats {
+    pub count: u64,
+    #[serde(rename = "sumMs")]
+    pub sum_ms: f64,
+    #[serde(rename = "maxMs")]
+    pub max_ms: f64,
+    /// Cumulative counts, Prometheus style
+    pub buckets: Vec<WaitTimeBucket>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WaitTimeBucket {
+    pub le: String,
+    pub count: u64,
+}
+
+impl HashPool {
+    pub fn new(workers: usize, queue_capacity: usize) -> Self {
+        let (sender, receiver) = sync_channel::<QueuedJob>(queue_capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let metrics = Arc::new(HashPoolMetrics::default());
+
+        for index in 0..workers {
+            let receiver = receiver.clone();
+            let metrics = metrics.clone();
+            thread::Builder::new()
+                .name(format!("hash-worker-{}", index))
+                .spawn(move || loop {
+                    // The lock is only held while waiting for the next job
+                    let next = receiver.lock().unwrap().recv();
+                    let Ok(queued) = next else { break };
+
+                    metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+                    metrics.record_wait(queued.enqueued_at.elapsed());
+                    metrics.busy_workers.fetch_add(1, Ordering::Relaxed);
+                    (queued.job)();
+                    metrics.busy_workers.fetch_sub(1, Ordering::Relaxed);
+                    metrics.completed.fetch_add(1, Ordering::Relaxed);
+                })
+                .expect("Failed to spawn hash worker thread");
+        }
+
+        Self {
+            sender,
+            workers,
+            queue_capacity,
+            metrics,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let default_workers = thread::available_parallelism().map_or(4, |n| n.get());
+        let workers = env_or("HASH_POOL_THREADS", default_workers).max(1);
+        let queue_capacity = env_or("HASH_POOL_QUEUE_SIZE", workers * 16);
+        Self::new(workers, queue_capacity)
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub async fn run<T, F>(&self, f: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            // The caller may have gone away (client disconnected), nothing to do then
+            let _ = result_tx.send(f());
+        });
+
+        self.metrics.queue_depth.fetch_add(1, Ordering::Relaxed);
+        match self.sender.try_send(QueuedJob {
+            job,
+            enqueued_at: Instant::now(),
+        }) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+                self.metrics.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(AppError::ServiceUnavailable(
+                    "Server is busy, please retry".to_string(),
+                ));
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+                return Err(AppError::InternalServerError(
+                    "Hash workers are not running".to_string(),
+                ));
+            }
+        }
+
+        result_rx
+            .await
+            .map_err(|_| AppError::InternalServerError("Hashing task failed".to_string()))
+    }
+
+    pub fn stats(&self) -> HashPoolStats {
+        let metrics = &self.metrics;
+
+        let mut cumulative = 0;
+        let buckets = metrics
+            .wait_buckets
+            .iter()
+            .enumerate()
+            .map(|(index, count)| {
+                cumulative += count.load(Ordering::Relaxed);
+                WaitTimeBucket {
+                    le: WAIT_BUCKETS_MS
+                        .get(index)
+                        .map_or_else(|| "+Inf".to_string(), |bound_ms| bound_ms.to_string()),
+                    count: cumulative,
+                }
+            })
+            .collect();
+
+        HashPoolStats {
+            workers: self.workers,
+            queue_capacity: self.queue_capacity,
+            queue_depth: metrics.queue_depth.load(Ordering::Relaxed),
+            busy_workers: metrics.busy_workers.load(Ordering::Relaxed),
+            completed: metrics.completed.load(Ordering::Relaxed),
+            rejected: metrics.rejected.load(Ordering::Relaxed),
+            wait_time: WaitTimeStats {
+                count: metrics.wait_count.load(Ordering::Relaxed),
+                sum_ms: metrics.wait_sum_us.load(Ordering::Relaxed) as f64 / 1000.0,
+                max_ms: metrics.wait_max_us.load(Ordering::Relaxed) as f64 / 1000.0,
+                buckets,
+            },
+        }
+    }
+}
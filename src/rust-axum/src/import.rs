@@ -0,0 +1,187 @@
+use anyhow::Context;
+use futures_util::{stream, StreamExt};
+use sqlx::PgPool;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::{hash_password, PasswordHashPolicy},
+    error::AppError,
+    hash_pool::HashPool,
+    models::{ImportReport, ImportRowResult, ImportUser},
+    sql::SQL_CREATE_USERS_BATCH,
+};
+
+/// Users inserted per statement
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ImportFormat {
+    /// Header row with `username,email,password,bio`, `bio` may be left out or empty
+    Csv,
+    /// One JSON object per line with the same fields
+    Ndjson,
+}
+
+impl ImportFormat {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.split(';').next().unwrap_or("").trim() {
+            "text/csv" => Some(Self::Csv),
+            "application/x-ndjson" | "application/jsonl" => Some(Self::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(Self::Csv),
+            "ndjson" | "jsonl" => Some(Self::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+/// Creates users in bulk, for seeding environments.
+///
+/// Each record is validated on its own and passwords are hashed on all hash pool workers at
+/// once. Valid users are then inserted in batches within one transaction; a record whose
+/// username or email already exists is reported and skipped, it does not fail the import.
+/// Imported users get no role, like users created by `POST /users`.
+pub async fn import_users(
+    db: &PgPool,
+    hash_pool: &HashPool,
+    policy: PasswordHashPolicy,
+    format: ImportFormat,
+    data: &[u8],
+) -> Result<ImportReport, AppError> {
+    let records = match format {
+        ImportFormat::Csv => parse_csv(data),
+        ImportFormat::Ndjson => parse_ndjson(data),
+    };
+
+    let mut rows: Vec<ImportRowResult> = (1..=records.len())
+        .map(|row| ImportRowResult { row, id: None, error: None })
+        .collect();
+    let mut usernames = HashSet::new();
+    let mut emails = HashSet::new();
+    let mut valid = Vec::new();
+    for (index, record) in records.into_iter().enumerate() {
+        let checked = record.and_then(|user| {
+            validate(&user)?;
+            // The first record wins, later ones would hit the unique constraints anyway
+            if !usernames.insert(user.username.clone()) {
+                return Err("Duplicate username in import".to_string());
+            }
+            if !emails.insert(user.email.clone()) {
+                return Err("Duplicate email in import".to_string());
+            }
+            Ok(user)
+        });
+        match checked {
+            Ok(user) => valid.push((index, user)),
+            Err(error) => rows[index].error = Some(error),
+        }
+    }
+
+    // One job per worker keeps the pool queue from overflowing into 503s
+    let hashed: Vec<_> = stream::iter(valid)
+        .map(|(index, user)| async move {
+            let password_hash = hash_password(hash_pool, &user.password, policy).await;
+            (index, user, password_hash)
+        })
+        .buffered(hash_pool.workers())
+        .collect()
+        .await;
+
+    let mut pending = Vec::with_capacity(hashed.len());
+    for (index, user, password_hash) in hashed {
+        match password_hash {
+            Ok(password_hash) => pending.push((index, user, password_hash)),
+            Err(e) => rows[index].error = Some(e.to_string()),
+        }
+    }
+
+    let mut tx = db.begin().await?;
+    for batch in pending.chunks(IMPORT_BATCH_SIZE) {
+        let created: Vec<(Uuid, String)> = sqlx::query_as(SQL_CREATE_USERS_BATCH)
+            .bind(batch.iter().map(|(_, user, _)| user.username.as_str()).collect::<Vec<_>>())
+            .bind(batch.iter().map(|(_, user, _)| user.email.as_str()).collect::<Vec<_>>())
+            .bind(batch.iter().map(|(_, _, hash)| hash.as_str()).collect::<Vec<_>>())
+            .bind(batch.iter().map(|(_, user, _)| user.bio.as_deref()).collect::<Vec<_>>())
+            .fetch_all(&mut *tx)
+            .await?;
+        let created: HashMap<String, Uuid> = created.into_iter().map(|(id, email)| (email, id)).collect();
+
+        for (index, user, _) in batch {
+            match created.get(&user.email) {
+                Some(id) => rows[*index].id = Some(id.to_string()),
+                None => rows[*index].error = Some("Username or email already taken".to_string()),
+            }
+        }
+    }
+    tx.commit().await?;
+
+    let imported = rows.iter().filter(|row| row.id.is_some()).count();
+    Ok(ImportReport {
+        imported,
+        failed: rows.len() - imported,
+        rows,
+    })
+}
+
+/// `rust-axum-api import-users <file>`: the import behind `POST /users/import`, for files
+/// too large for a request. The file extension picks the format.
+pub async fn run_cli(db: &PgPool, policy: PasswordHashPolicy, path: Option<&str>) -> anyhow::Result<()> {
+    let path = path.context("Usage: rust-axum-api import-users <file.csv|file.ndjson>")?;
+    let format = ImportFormat::from_path(Path::new(path))
+        .context("Import file must end in .csv, .ndjson or .jsonl")?;
+    let data = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path))?;
+
+    let report = import_users(db, &HashPool::from_env(), policy, format, &data).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    tracing::info!("Imported {} users, {} failed", report.imported, report.failed);
+    Ok(())
+}
+
+fn parse_csv(data: &[u8]) -> Vec<Result<ImportUser, String>> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::Headers)
+        .from_reader(data)
+        .deserialize()
+        .map(|record| {
+            // Spaces around a password are part of it, so only these columns are trimmed
+            record
+                .map(|user: ImportUser| ImportUser {
+                    username: user.username.trim().to_string(),
+                    email: user.email.trim().to_string(),
+                    ..user
+                })
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+fn parse_ndjson(data: &[u8]) -> Vec<Result<ImportUser, String>> {
+    data.split(|byte| *byte == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty())
+        .map(|line| serde_json::from_slice(line).map_err(|e| e.to_string()))
+        .collect()
+}
+
+fn validate(user: &ImportUser) -> Result<(), String> {
+    if user.username.trim().is_empty() || user.username.len() > 255 {
+        return Err("Username must be 1 to 255 characters".to_string());
+    }
+    if user.email.len() > 255 || !user.email.contains('@') {
+        return Err("Invalid email".to_string());
+    }
+    if user.password.is_empty() {
+        return Err("Password is required".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,275 @@
+use axum::http::HeaderMap;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{auth::env_or, error::AppError};
+
+/// In-memory failed login tracking, per account and per client IP.
+///
+/// Once a key reaches its failure threshold it is locked out, and every further
+/// failure doubles the lockout up to `max_lockout`. Counters are forgotten after
+/// `failure_window` without a new failure.
+pub struct LoginThrottle {
+    config: ThrottleConfig,
+    accounts: Mutex<HashMap<String, FailureState>>,
+    ips: Mutex<HashMap<IpAddr, FailureState>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThrottleConfig {
+    pub max_account_failures: u32,
+    pub max_ip_failures: u32,
+    pub base_lockout: Duration,
+    pub max_lockout: Duration,
+    pub failure_window: Duration,
+    /// Use the first `X-Forwarded-For` entry as client IP (only behind a trusted proxy)
+    pub trust_forwarded_for: bool,
+}
+
+impl ThrottleConfig {
+    pub fn from_env() -> Self {
+        Self {
+            max_account_failures: env_or("LOGIN_MAX_FAILURES_PER_ACCOUNT", 5),
+            max_ip_failures: env_or("LOGIN_MAX_FAILURES_PER_IP", 20),
+            base_lockout: Duration::from_secs(env_or("LOGIN_LOCKOUT_BASE_SECONDS", 30)),
+            max_lockout: Duration::from_secs(env_or("LOGIN_LOCKOUT_MAX_SECONDS", 900)),
+            failure_window: Duration::from_secs(env_or("LOGIN_FAILURE_WINDOW_SECONDS", 900)),
+            trust_forwarded_for: env_or("LOGIN_TRUST_X_FORWARDED_FOR", false),
+        }
+    }
+}
+
+/// `Retry-After` when the attempts already in flight would reach the threshold
+const IN_FLIGHT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+struct FailureState {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+    /// Attempts between `check` and their outcome
+    in_flight: u32,
+}
+
+impl FailureState {
+    fn new(now: Instant) -> Self {
+        Self {
+            failures: 0,
+            last_failure: now,
+            locked_until: None,
+            in_flight: 0,
+        }
+    }
+
+    fn retry_after(&self, now: Instant) -> Option<Duration> {
+        self.locked_until
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| locked_until - now)
+    }
+
+    /// Attempts in flight count as failures until they are known not to be, so a burst
+    /// of concurrent guesses cannot all pass before the first one is recorded.
+    fn wait(&self, now: Instant, threshold: u32, config: &ThrottleConfig) -> Option<Duration> {
+        if let Some(wait) = self.retry_after(now) {
+            return Some(wait);
+        }
+        // Past the threshold, one attempt at a time: its failure extends the lockout
+        let allowed = threshold.saturating_sub(self.recent_failures(now, config)).max(1);
+        (self.in_flight >= allowed).then_some(IN_FLIGHT_RETRY_AFTER)
+    }
+
+    /// Failures no longer count once `failure_window` has passed since the last one.
+    fn recent_failures(&self, now: Instant, config: &ThrottleConfig) -> u32 {
+        if now - self.last_failure > config.failure_window {
+            0
+        } else {
+            self.failures
+        }
+    }
+
+    fn is_stale(&self, now: Instant, config: &ThrottleConfig) -> bool {
+        self.in_flight == 0
+            && self.retry_after(now).is_none()
+            && now - self.last_failure > config.failure_window
+    }
+}
+
+/// A login attempt let through by `LoginThrottle::check`. Dropping it without an outcome
+/// (e.g. on an error or a pending second factor) releases it without counting a failure.
+pub struct LoginAttempt<'a> {
+    throttle: &'a LoginThrottle,
+    account: String,
+    ip: IpAddr,
+}
+
+impl LoginAttempt<'_> {
+    pub fn record_failure(self) {
+        let now = Instant::now();
+        let throttle = self.throttle;
+        throttle.bump(&throttle.accounts, self.account.clone(), throttle.config.max_account_failures, now);
+        throttle.bump(&throttle.ips, self.ip, throttle.config.max_ip_failures, now);
+    }
+
+    /// A successful login clears the account's failures. The IP counter is kept so a
+    /// credential stuffer cannot reset it with one known-good account.
+    pub fn record_success(self) {
+        // Kept for the attempts still in flight, maintenance removes it once they are done
+        if let Some(state) = self.throttle.accounts.lock().unwrap().get_mut(&self.account) {
+            state.failures = 0;
+            state.locked_until = None;
+        }
+    }
+}
+
+impl Drop for LoginAttempt<'_> {
+    fn drop(&mut self) {
+        if let Some(state) = self.throttle.accounts.lock().unwrap().get_mut(&self.account) {
+            state.in_flight -= 1;
+        }
+        if let Some(state) = self.throttle.ips.lock().unwrap().get_mut(&self.ip) {
+            state.in_flight -= 1;
+        }
+    }
+}
+
+impl LoginThrottle {
+    pub fn new(config: ThrottleConfig) -> Self {
+        Self {
+            config,
+            accounts: Mutex::new(HashMap::new()),
+            ips: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Rejects the attempt with `429` while the account or the client IP is locked out,
+    /// otherwise reserves it until its outcome is recorded.
+    pub fn check(&self, account: &str, ip: IpAddr) -> Result<LoginAttempt<'_>, AppError> {
+        let now = Instant::now();
+        let mut accounts = self.accounts.lock().unwrap();
+        let mut ips = self.ips.lock().unwrap();
+        let account_state = accounts
+            .entry(account.to_string())
+            .or_insert_with(|| FailureState::new(now));
+        let ip_state = ips.entry(ip).or_insert_with(|| FailureState::new(now));
+
+        let account_wait = account_state.wait(now, self.config.max_account_failures, &self.config);
+        let ip_wait = ip_state.wait(now, self.config.max_ip_failures, &self.config);
+        if let Some(wait) = account_wait.max(ip_wait) {
+            return Err(AppError::TooManyRequests {
+                message: "Too many failed login attempts".to_string(),
+                // Round up so clients never retry while still locked out
+                retry_after_secs: wait.as_secs() + u64::from(wait.subsec_nanos() > 0),
+            });
+        }
+
+        account_state.in_flight += 1;
+        ip_state.in_flight += 1;
+        Ok(LoginAttempt {
+            throttle: self,
+            account: account.to_string(),
+            ip,
+        })
+    }
+
+    fn bump<K: Eq + Hash>(
+        &self,
+        map: &Mutex<HashMap<K, FailureState>>,
+        key: K,
+        threshold: u32,
+        now: Instant,
+    ) {
+        let mut map = map.lock().unwrap();
+        let state = map.entry(key).or_insert_with(|| FailureState::new(now));
+
+        // Not `is_stale`: the attempt being recorded is still in flight
+        state.failures = state.recent_failures(now, &self.config) + 1;
+        state.last_failure = now;
+
+        if state.failures >= threshold {
+            let doublings = (state.failures - threshold).min(16);
+            let lockout = self
+                .config
+                .base_lockout
+                .saturating_mul(1 << doublings)
+                .min(self.config.max_lockout);
+            state.locked_until = Some(now + lockout);
+        }
+    }
+
+    pub fn client_ip(&self, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+        if self.config.trust_forwarded_for {
+            let forwarded = headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .and_then(|value| value.trim().parse().ok());
+            if let Some(ip) = forwarded {
+                return ip;
+            }
+        }
+        peer.ip()
+    }
+
+    pub fn spawn_maintenance(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let now = Instant::now();
+                self.accounts
+                    .lock()
+                    .unwrap()
+                    .retain(|_, state| !state.is_stale(now, &self.config));
+                self.ips
+                    .lock()
+                    .unwrap()
+                    .retain(|_, state| !state.is_stale(now, &self.config));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle(failure_window: Duration) -> LoginThrottle {
+        LoginThrottle::new(ThrottleConfig {
+            max_account_failures: 5,
+            max_ip_failures: 20,
+            base_lockout: Duration::from_secs(30),
+            max_lockout: Duration::from_secs(900),
+            failure_window,
+            trust_forwarded_for: false,
+        })
+    }
+
+    fn fail(throttle: &LoginThrottle, times: usize) {
+        for _ in 0..times {
+            throttle.check("user@example.com", IpAddr::from([127, 0, 0, 1])).unwrap().record_failure();
+        }
+    }
+
+    #[test]
+    fn locks_out_at_threshold() {
+        let throttle = throttle(Duration::from_secs(900));
+        fail(&throttle, 5);
+
+        let result = throttle.check("user@example.com", IpAddr::from([127, 0, 0, 1]));
+        assert!(matches!(result, Err(AppError::TooManyRequests { .. })));
+    }
+
+    #[test]
+    fn forgets_failures_after_window() {
+        let throttle = throttle(Duration::from_millis(50));
+        fail(&throttle, 4);
+        std::thread::sleep(Duration::from_millis(100));
+        fail(&throttle, 4);
+
+        assert!(throttle.check("user@example.com", IpAddr::from([127, 0, 0, 1])).is_ok());
+    }
+}
@@ -0,0 +1,87 @@
+use rand::RngCore;
+use std::time::{SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::error::AppError;
+
+/// Lifetime of the "mfa pending" token returned by `login` to enrolled users
+pub const MFA_CHALLENGE_EXPIRE_MINUTES: i64 = 5;
+/// Wrong codes accepted per "mfa pending" token before it stops working
+pub const MFA_MAX_ATTEMPTS: i32 = 5;
+
+const TOTP_STEP_SECONDS: u64 = 30;
+const TOTP_DIGITS: usize = 6;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// New random TOTP secret, base32 encoded as authenticator apps expect it.
+pub fn generate_totp_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    Secret::Raw(bytes.to_vec()).to_encoded().to_string()
+}
+
+fn totp(secret: &str, issuer: Option<&str>, account_name: &str) -> Result<TOTP, AppError> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|_| AppError::InternalServerError("Invalid TOTP secret".to_string()))?;
+
+    // SHA-1, 6 digits, 30 seconds: the only parameters every authenticator app supports
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        1,
+        TOTP_STEP_SECONDS,
+        secret,
+        issuer.map(str::to_string),
+        account_name.to_string(),
+    )
+    .map_err(|_| AppError::InternalServerError("Invalid TOTP parameters".to_string()))
+}
+
+/// `otpauth://` URI for authenticator apps, usually shown as a QR code.
+pub fn otpauth_uri(secret: &str, issuer: &str, account_name: &str) -> Result<String, AppError> {
+    // ':' separates issuer and account in the URI label
+    Ok(totp(secret, Some(issuer), &account_name.replace(':', ""))?.get_url())
+}
+
+/// Returns the time step the code was generated for, allowing one step of clock drift.
+/// Callers must reject steps that are not newer than the last accepted one.
+pub fn verify_totp(secret: &str, code: &str) -> Result<Option<i64>, AppError> {
+    let totp = totp(secret, None, "")?;
+    let current_step = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock after 1970")
+        .as_secs()
+        / TOTP_STEP_SECONDS;
+
+    let matching_step = [current_step - 1, current_step, current_step + 1]
+        .into_iter()
+        .find(|step| bool::from(totp.generate(step * TOTP_STEP_SECONDS).as_bytes().ct_eq(code.as_bytes())));
+
+    Ok(matching_step.map(|step| step as i64))
+}
+
+pub fn is_totp_code(code: &str) -> bool {
+    code.len() == TOTP_DIGITS && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Recovery codes are shown once as `xxxx-xxxx-xxxx-xxxx`, only their hash is stored.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 8];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let code = hex::encode(bytes);
+            format!("{}-{}-{}-{}", &code[0..4], &code[4..8], &code[8..12], &code[12..16])
+        })
+        .collect()
+}
+
+/// Users may type recovery codes without dashes or in upper case.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
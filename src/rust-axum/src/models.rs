@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
@@ -9,6 +9,76 @@ pub struct LoginCredentials {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogoutRequest {
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyCreate {
+    pub name: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaCode {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaLogin {
+    #[serde(rename = "mfaToken")]
+    pub mfa_token: String,
+    /// TOTP code or recovery code
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordChange {
+    #[serde(rename = "currentPassword")]
+    pub current_password: String,
+    #[serde(rename = "newPassword")]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetConfirm {
+    pub token: String,
+    #[serde(rename = "newPassword")]
+    pub new_password: String,
+}
+
+/// `PATCH /auth/me`: omitted fields are left unchanged, `"bio": null` clears the bio
+#[derive(Debug, Deserialize)]
+pub struct MeUpdate {
+    #[serde(default)]
+    pub username: Patch<String>,
+    #[serde(default)]
+    pub email: Patch<String>,
+    #[serde(default)]
+    pub bio: Patch<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailChangeConfirm {
+    pub token: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateUser {
     pub username: String,
@@ -16,11 +86,84 @@ pub struct CreateUser {
     pub password: String,
 }
 
+/// One record of a bulk import, the same fields in CSV (header row) and NDJSON
+#[derive(Debug, Deserialize)]
+pub struct ImportUser {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+    pub bio: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterUser {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+    #[serde(rename = "inviteCode")]
+    pub invite_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InviteCreate {
+    pub email: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct UpdateUser {
     pub bio: Option<String>,
 }
 
+/// A field of a PATCH body: absent (leave unchanged), `null` (clear) or a value.
+/// Fields of this type need `#[serde(default)]` so that absent means `Missing`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Patch<T> {
+    #[default]
+    Missing,
+    Null,
+    Value(T),
+}
+
+impl<T> Patch<T> {
+    /// `None` when absent, otherwise the new value of a nullable column.
+    pub fn as_update(&self) -> Option<Option<&T>> {
+        match self {
+            Patch::Missing => None,
+            Patch::Null => Some(None),
+            Patch::Value(value) => Some(Some(value)),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(value) => Patch::Value(value),
+            None => Patch::Null,
+        })
+    }
+}
+
+/// `PATCH /users/{userId}`: only the fields present in the body are updated
+#[derive(Debug, Deserialize)]
+pub struct UserPatch {
+    #[serde(default)]
+    pub username: Patch<String>,
+    #[serde(default)]
+    pub email: Patch<String>,
+    #[serde(default)]
+    pub bio: Patch<String>,
+    #[serde(default, rename = "isAdmin")]
+    pub is_admin: Patch<bool>,
+    #[serde(default)]
+    pub password: Patch<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserRolesUpdate {
+    pub roles: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PostCreate {
     pub content: String,
@@ -36,6 +179,107 @@ pub struct CommentCreate {
 pub struct LoginResponse {
     #[serde(rename = "accessToken")]
     pub access_token: String,
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
+}
+
+/// Returned by `login` instead of tokens when the user has TOTP enabled
+#[derive(Debug, Serialize)]
+pub struct MfaChallenge {
+    #[serde(rename = "mfaRequired")]
+    pub mfa_required: bool,
+    #[serde(rename = "mfaToken")]
+    pub mfa_token: String,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodes {
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Invite {
+    #[serde(rename = "inviteCode")]
+    pub invite_code: String,
+    pub email: Option<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(rename = "revokedAt")]
+    pub revoked_at: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Session {
+    pub id: String,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "ipAddress")]
+    pub ip_address: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: DateTime<Utc>,
+    /// The session of the token making the request
+    pub current: bool,
+}
+
+/// `PATCH /auth/me`: the email only changes once the new address is confirmed
+#[derive(Debug, Serialize)]
+pub struct MeUpdated {
+    #[serde(flatten)]
+    pub user: User,
+    #[serde(rename = "pendingEmail", skip_serializing_if = "Option::is_none")]
+    pub pending_email: Option<String>,
+}
+
+/// Short-lived access token for an admin acting as another user, without refresh token
+#[derive(Debug, Serialize)]
+pub struct ImpersonationToken {
+    #[serde(rename = "accessToken")]
+    pub access_token: String,
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime<Utc>,
+}
+
+/// `GET /auth/me`: the user, plus the admin behind an impersonation token
+#[derive(Debug, Serialize)]
+pub struct CurrentUser {
+    #[serde(flatten)]
+    pub user: User,
+    #[serde(rename = "impersonatedBy", skip_serializing_if = "Option::is_none")]
+    pub impersonated_by: Option<String>,
+}
+
+/// Returned once, at creation: the key itself is never stored.
+#[derive(Debug, Serialize)]
+pub struct ApiKeyCreated {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -48,6 +292,68 @@ pub struct User {
     pub created_at: DateTime<Utc>,
 }
 
+/// Public view of a user: no email, plus activity counts
+#[derive(Debug, Serialize)]
+pub struct UserProfile {
+    pub id: String,
+    pub username: String,
+    pub bio: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "postsCount")]
+    pub posts_count: i64,
+    #[serde(rename = "commentsCount")]
+    pub comments_count: i64,
+    #[serde(rename = "likesReceived")]
+    pub likes_received: i64,
+    #[serde(rename = "followersCount")]
+    pub followers_count: i64,
+    #[serde(rename = "followingCount")]
+    pub following_count: i64,
+}
+
+/// Entry of a followers or following list
+#[derive(Debug, Serialize)]
+pub struct FollowUser {
+    pub id: String,
+    pub username: String,
+    pub bio: Option<String>,
+    #[serde(rename = "followedAt")]
+    pub followed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Role {
+    pub name: String,
+    pub description: String,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub failed: usize,
+    pub rows: Vec<ImportRowResult>,
+}
+
+/// Outcome of one record: the new user's `id`, or why it was skipped
+#[derive(Debug, Serialize)]
+pub struct ImportRowResult {
+    /// 1-based record number, the CSV header and blank lines are not counted
+    pub row: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserRoles {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    pub roles: Vec<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct Post {
     pub id: String,
@@ -71,6 +377,15 @@ pub struct Comment {
     pub created_at: DateTime<Utc>,
 }
 
+/// A post liked by the user, as listed in their data export
+#[derive(Debug, Serialize)]
+pub struct Like {
+    #[serde(rename = "postId")]
+    pub post_id: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
 // Database row structs
 #[derive(Debug, sqlx::FromRow)]
 pub struct UserRow {
@@ -107,11 +422,117 @@ pub struct CommentRow {
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, sqlx::FromRow)]
+pub struct LikeRow {
+    pub post_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct RoleRow {
+    pub name: String,
+    pub description: String,
+    pub permissions: Vec<String>,
+}
+
 #[derive(Debug, sqlx::FromRow)]
 pub struct LoginRow {
     pub id: Uuid,
     pub password_hash: String,
-    pub is_admin: bool,
+    pub permissions: Vec<String>,
+    pub mfa_enabled: bool,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct RefreshTokenRow {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ApiKeyRow {
+    pub id: Uuid,
+    pub name: String,
+    pub key_prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct UserProfileRow {
+    pub id: Uuid,
+    pub username: String,
+    pub bio: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub posts_count: i64,
+    pub comments_count: i64,
+    pub likes_received: i64,
+    pub followers_count: i64,
+    pub following_count: i64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct FollowUserRow {
+    pub id: Uuid,
+    pub username: String,
+    pub bio: Option<String>,
+    pub followed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct EmailChangeRow {
+    pub user_id: Uuid,
+    pub new_email: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct SessionRow {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ApiKeyAuthRow {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct TotpRow {
+    pub secret: String,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct MfaChallengeRow {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub cookie_mode: bool,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct OidcStateRow {
+    pub code_verifier: String,
+    pub nonce: String,
+    pub cookie_mode: bool,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct RevokedTokenRow {
+    pub jti: String,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: DateTime<Utc>,
 }
 
 // Conversion implementations
@@ -127,6 +548,57 @@ impl From<UserRow> for User {
     }
 }
 
+impl From<UserProfileRow> for UserProfile {
+    fn from(row: UserProfileRow) -> Self {
+        Self {
+            id: row.id.to_string(),
+            username: row.username,
+            bio: row.bio,
+            created_at: row.created_at,
+            posts_count: row.posts_count,
+            comments_count: row.comments_count,
+            likes_received: row.likes_received,
+            followers_count: row.followers_count,
+            following_count: row.following_count,
+        }
+    }
+}
+
+impl From<FollowUserRow> for FollowUser {
+    fn from(row: FollowUserRow) -> Self {
+        Self {
+            id: row.id.to_string(),
+            username: row.username,
+            bio: row.bio,
+            followed_at: row.followed_at,
+        }
+    }
+}
+
+impl From<ApiKeyRow> for ApiKey {
+    fn from(row: ApiKeyRow) -> Self {
+        Self {
+            id: row.id.to_string(),
+            name: row.name,
+            prefix: row.key_prefix,
+            scopes: row.scopes,
+            expires_at: row.expires_at,
+            revoked_at: row.revoked_at,
+            created_at: row.created_at,
+        }
+    }
+}
+
+impl From<RoleRow> for Role {
+    fn from(row: RoleRow) -> Self {
+        Self {
+            name: row.name,
+            description: row.description,
+            permissions: row.permissions,
+        }
+    }
+}
+
 impl From<PostRow> for Post {
     fn from(row: PostRow) -> Self {
         Self {
@@ -162,3 +634,12 @@ impl From<CommentRow> for Comment {
         }
     }
 }
+
+impl From<LikeRow> for Like {
+    fn from(row: LikeRow) -> Self {
+        Self {
+            post_id: row.post_id.to_string(),
+            created_at: row.created_at,
+        }
+    }
+}
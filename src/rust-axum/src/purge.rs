@@ -0,0 +1,42 @@
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::sql::SQL_PURGE_DELETED_USERS;
+
+/// Users anonymised per statement, so a large backlog does not hold locks for long
+const PURGE_BATCH_SIZE: i64 = 100;
+
+/// Periodically anonymises users whose soft delete is older than `grace_days`.
+///
+/// Their row is kept with a placeholder username and email, and without password,
+/// roles or credentials, so posts, comments and likes (and the `likes_count` of
+/// other users' posts) are left as they were. Instances can run it concurrently:
+/// each batch skips rows locked by another instance.
+pub fn spawn_user_purge(db: PgPool, grace_days: i64, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match purge_deleted_users(&db, grace_days).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!("Purged {} deleted users", purged),
+                Err(e) => tracing::warn!("Failed to purge deleted users: {}", e),
+            }
+        }
+    });
+}
+
+async fn purge_deleted_users(db: &PgPool, grace_days: i64) -> Result<i64, sqlx::Error> {
+    let mut total = 0;
+    loop {
+        let purged: i64 = sqlx::query_scalar(SQL_PURGE_DELETED_USERS)
+            .bind(grace_days)
+            .bind(PURGE_BATCH_SIZE)
+            .fetch_one(db)
+            .await?;
+        total += purged;
+        if purged < PURGE_BATCH_SIZE {
+            return Ok(total);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+use uuid::Uuid;
+
+use crate::{error::AppError, models::RevokedTokenRow, sql::*};
+
+/// Denylist of revoked access tokens, keyed by their `jti` claim, or by their `sid` claim
+/// when a whole session is revoked.
+///
+/// Lookups only read the in-memory cache so `auth_middleware` never waits on the
+/// database. Postgres is the source of truth: each instance periodically pulls
+/// revocations made by other instances and drops entries whose token has expired.
+pub struct TokenDenylist {
+    db: PgPool,
+    entries: RwLock<HashMap<String, DateTime<Utc>>>,
+    synced_until: Mutex<DateTime<Utc>>,
+}
+
+impl TokenDenylist {
+    pub async fn load(db: PgPool) -> Result<Self, sqlx::Error> {
+        let denylist = Self {
+            db,
+            entries: RwLock::new(HashMap::new()),
+            synced_until: Mutex::new(DateTime::UNIX_EPOCH),
+        };
+        denylist.sync().await?;
+        Ok(denylist)
+    }
+
+    pub fn is_revoked(&self, jti: &str) -> bool {
+        self.entries.read().unwrap().contains_key(jti)
+    }
+
+    pub async fn revoke(
+        &self,
+        jti: &str,
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        sqlx::query(SQL_CREATE_REVOKED_TOKEN)
+            .bind(jti)
+            .bind(user_id)
+            .bind(expires_at)
+            .execute(&self.db)
+            .await?;
+
+        self.entries
+            .write()
+            .unwrap()
+            .insert(jti.to_string(), expires_at);
+        Ok(())
+    }
+
+    /// Prunes expired entries and pulls revocations recorded since the last sync.
+    async fn sync(&self) -> Result<(), sqlx::Error> {
+        let synced_until = *self.synced_until.lock().unwrap();
+        // Overlap the window so rows from transactions that committed late are not missed
+        let since = synced_until - chrono::Duration::minutes(1);
+
+        let rows: Vec<RevokedTokenRow> = sqlx::query_as(SQL_LIST_REVOKED_TOKENS_SINCE)
+            .bind(since)
+            .fetch_all(&self.db)
+            .await?;
+
+        let now = Utc::now();
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|_, expires_at| *expires_at > now);
+
+        let mut latest = synced_until;
+        for row in rows {
+            latest = latest.max(row.revoked_at);
+            entries.insert(row.jti, row.expires_at);
+        }
+        *self.synced_until.lock().unwrap() = latest;
+
+        Ok(())
+    }
+
+    pub fn spawn_maintenance(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // The first tick completes immediately and `load` has just synced
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = sqlx::query(SQL_PRUNE_REVOKED_TOKENS).execute(&self.db).await {
+                    tracing::warn!("Failed to prune revoked tokens: {}", e);
+                }
+                if let Err(e) = self.sync().await {
+                    tracing::warn!("Failed to sync revoked tokens: {}", e);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::bail;
+use axum::http::{HeaderMap, Method};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use subtle::ConstantTimeEq;
+
+use crate::{
+    auth::{env_or, generate_opaque_token, AuthConfig},
+    error::AppError,
+    models::LoginResponse,
+};
+
+pub const SESSION_COOKIE: &str = "session";
+pub const REFRESH_COOKIE: &str = "refresh_token";
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+pub const OIDC_STATE_COOKIE: &str = "oidc_state";
+
+/// The refresh cookie is only sent to `/auth/refresh` and `/auth/logout`
+const REFRESH_COOKIE_PATH: &str = "/auth";
+/// The OIDC state cookie is only sent back to `/auth/oidc/callback`
+const OIDC_STATE_COOKIE_PATH: &str = "/auth/oidc";
+
+/// Attributes of the cookies issued by `POST /auth/login?mode=cookie`.
+///
+/// The access token goes in an HttpOnly `session` cookie and the refresh token in an
+/// HttpOnly cookie scoped to `/auth`. `csrf_token` is readable by scripts: requests
+/// authenticated by cookie must echo it in `X-CSRF-Token` unless their method is safe.
+#[derive(Debug, Clone)]
+pub struct CookieConfig {
+    pub secure: bool,
+    pub same_site: SameSite,
+}
+
+impl CookieConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let same_site = match std::env::var("AUTH_COOKIE_SAMESITE").as_deref().unwrap_or("lax") {
+            "lax" => SameSite::Lax,
+            "strict" => SameSite::Strict,
+            other => bail!("Unsupported AUTH_COOKIE_SAMESITE: {} (expected lax or strict)", other),
+        };
+
+        Ok(Self {
+            secure: env_or("AUTH_COOKIE_SECURE", true),
+            same_site,
+        })
+    }
+
+    fn cookie(&self, name: &'static str, value: String, path: &'static str, max_age: time::Duration) -> Cookie<'static> {
+        Cookie::build((name, value))
+            .path(path)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .max_age(max_age)
+            .build()
+    }
+}
+
+/// Adds the session, refresh and CSRF cookies for a freshly issued token pair.
+pub fn set_session_cookies(jar: CookieJar, tokens: LoginResponse, config: &AuthConfig) -> CookieJar {
+    let cookies = &config.cookies;
+    let refresh_max_age = time::Duration::days(config.refresh_token_expire_days);
+
+    let mut session = cookies.cookie(
+        SESSION_COOKIE,
+        tokens.access_token,
+        "/",
+        time::Duration::minutes(config.jwt_expire_minutes),
+    );
+    session.set_http_only(true);
+    let mut refresh = cookies.cookie(REFRESH_COOKIE, tokens.refresh_token, REFRESH_COOKIE_PATH, refresh_max_age);
+    refresh.set_http_only(true);
+    let csrf = cookies.cookie(CSRF_COOKIE, generate_opaque_token(), "/", refresh_max_age);
+
+    jar.add(session).add(refresh).add(csrf)
+}
+
+pub fn clear_session_cookies(jar: CookieJar) -> CookieJar {
+    jar.remove(Cookie::build(SESSION_COOKIE).path("/"))
+        .remove(Cookie::build(REFRESH_COOKIE).path(REFRESH_COOKIE_PATH))
+        .remove(Cookie::build(CSRF_COOKIE).path("/"))
+}
+
+/// Ties an OIDC login to the browser that started it, see `verify_oidc_state`.
+pub fn set_oidc_state_cookie(jar: CookieJar, state: String, config: &CookieConfig, max_age: time::Duration) -> CookieJar {
+    let mut cookie = config.cookie(OIDC_STATE_COOKIE, state, OIDC_STATE_COOKIE_PATH, max_age);
+    // The callback is a cross-site navigation from the IdP, which `Strict` would strip the cookie from
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_http_only(true);
+    jar.add(cookie)
+}
+
+pub fn clear_oidc_state_cookie(jar: CookieJar) -> CookieJar {
+    jar.remove(Cookie::build(OIDC_STATE_COOKIE).path(OIDC_STATE_COOKIE_PATH))
+}
+
+/// Without this check, a victim sent to the callback URL of the attacker's own login would
+/// be signed in to the attacker's account (login CSRF).
+pub fn verify_oidc_state(state: &str, jar: &CookieJar) -> Result<(), AppError> {
+    match jar.get(OIDC_STATE_COOKIE) {
+        Some(cookie) if bool::from(cookie.value().as_bytes().ct_eq(state.as_bytes())) => Ok(()),
+        _ => Err(AppError::BadRequest("Invalid or expired state".to_string())),
+    }
+}
+
+/// Double-submit check for requests authenticated by cookie: a cross-site page can make the
+/// browser send the cookies, but cannot read `csrf_token` to copy it into the header.
+pub fn verify_csrf(method: &Method, headers: &HeaderMap, jar: &CookieJar) -> Result<(), AppError> {
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(());
+    }
+
+    let header_token = headers.get(CSRF_HEADER).and_then(|value| value.to_str().ok());
+    let cookie_token = jar.get(CSRF_COOKIE).map(|cookie| cookie.value());
+
+    match (header_token, cookie_token) {
+        (Some(header_token), Some(cookie_token))
+            if bool::from(header_token.as_bytes().ct_eq(cookie_token.as_bytes())) =>
+        {
+            Ok(())
+        }
+        _ => Err(AppError::Forbidden("Invalid CSRF token".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oidc_state_must_match_cookie() {
+        let jar = CookieJar::new().add(Cookie::new(OIDC_STATE_COOKIE, "state-1"));
+
+        assert!(verify_oidc_state("state-1", &jar).is_ok());
+        assert!(verify_oidc_state("state-2", &jar).is_err());
+        assert!(verify_oidc_state("state-1", &CookieJar::new()).is_err());
+    }
+}
@@ -1,27 +1,125 @@
 // SQL query constants - loaded at compile time for better performance
 
 // Auth
-pub const SQL_LOGIN: &str = include_str!("../../../database/queries/auth/login.sql");
-pub const SQL_ME: &str = include_str!("../../../database/queries/auth/me.sql");
+pub const SQL_LOGIN_WITH_PERMISSIONS: &str = include_str!("../../../database/queries/auth/login_with_permissions.sql");
+pub const SQL_ME: &str = include_str!("../../../database/queries/users/get_active.sql");
+pub const SQL_GET_PASSWORD_HASH: &str = include_str!("../../../database/queries/auth/password_hash.sql");
 
-// Users
+// Refresh tokens
+pub const SQL_CREATE_REFRESH_TOKEN: &str = include_str!("../../../database/queries/refresh_tokens/create.sql");
+pub const SQL_GET_REFRESH_TOKEN: &str = include_str!("../../../database/queries/refresh_tokens/get.sql");
+pub const SQL_REVOKE_REFRESH_TOKEN: &str = include_str!("../../../database/queries/refresh_tokens/revoke.sql");
+pub const SQL_REVOKE_REFRESH_TOKEN_FAMILY: &str = include_str!("../../../database/queries/refresh_tokens/revoke_family.sql");
+pub const SQL_REVOKE_USER_REFRESH_TOKENS: &str = include_str!("../../../database/queries/refresh_tokens/revoke_user.sql");
+
+// Sessions
+pub const SQL_CREATE_SESSION: &str = include_str!("../../../database/queries/sessions/create.sql");
+pub const SQL_TOUCH_SESSION: &str = include_str!("../../../database/queries/sessions/touch.sql");
+pub const SQL_LIST_SESSIONS: &str = include_str!("../../../database/queries/sessions/list.sql");
+pub const SQL_REVOKE_SESSION: &str = include_str!("../../../database/queries/sessions/revoke.sql");
+pub const SQL_REVOKE_USER_SESSIONS: &str = include_str!("../../../database/queries/sessions/revoke_user.sql");
+
+// API keys
+pub const SQL_CREATE_API_KEY: &str = include_str!("../../../database/queries/api_keys/create.sql");
+pub const SQL_LIST_API_KEYS: &str = include_str!("../../../database/queries/api_keys/list.sql");
+pub const SQL_REVOKE_API_KEY: &str = include_str!("../../../database/queries/api_keys/revoke.sql");
+pub const SQL_AUTHENTICATE_API_KEY: &str = include_str!("../../../database/queries/api_keys/authenticate.sql");
+
+// MFA
+pub const SQL_ENROLL_TOTP: &str = include_str!("../../../database/queries/mfa/enroll_totp.sql");
+pub const SQL_GET_TOTP: &str = include_str!("../../../database/queries/mfa/get_totp.sql");
+pub const SQL_CONFIRM_TOTP: &str = include_str!("../../../database/queries/mfa/confirm_totp.sql");
+pub const SQL_USE_TOTP_STEP: &str = include_str!("../../../database/queries/mfa/use_totp_step.sql");
+pub const SQL_DELETE_TOTP: &str = include_str!("../../../database/queries/mfa/delete_totp.sql");
+pub const SQL_CREATE_RECOVERY_CODES: &str = include_str!("../../../database/queries/mfa/create_recovery_codes.sql");
+pub const SQL_USE_RECOVERY_CODE: &str = include_str!("../../../database/queries/mfa/use_recovery_code.sql");
+pub const SQL_DELETE_RECOVERY_CODES: &str = include_str!("../../../database/queries/mfa/delete_recovery_codes.sql");
+pub const SQL_CREATE_MFA_CHALLENGE: &str = include_str!("../../../database/queries/mfa/create_challenge.sql");
+pub const SQL_GET_MFA_CHALLENGE: &str = include_str!("../../../database/queries/mfa/get_challenge.sql");
+pub const SQL_FAIL_MFA_CHALLENGE: &str = include_str!("../../../database/queries/mfa/fail_challenge.sql");
+pub const SQL_COMPLETE_MFA_CHALLENGE: &str = include_str!("../../../database/queries/mfa/complete_challenge.sql");
+
+// Revoked access tokens
+pub const SQL_CREATE_REVOKED_TOKEN: &str = include_str!("../../../database/queries/revoked_tokens/create.sql");
+pub const SQL_LIST_REVOKED_TOKENS_SINCE: &str = include_str!("../../../database/queries/revoked_tokens/list_since.sql");
+pub const SQL_PRUNE_REVOKED_TOKENS: &str = include_str!("../../../database/queries/revoked_tokens/prune.sql");
+
+// Password resets
+pub const SQL_CREATE_PASSWORD_RESET: &str = include_str!("../../../database/queries/password_resets/create.sql");
+pub const SQL_GET_VALID_PASSWORD_RESET: &str = include_str!("../../../database/queries/password_resets/get_valid.sql");
+pub const SQL_CONSUME_PASSWORD_RESET: &str = include_str!("../../../database/queries/password_resets/consume.sql");
+pub const SQL_INVALIDATE_USER_PASSWORD_RESETS: &str = include_str!("../../../database/queries/password_resets/invalidate_user.sql");
+
+// Email changes
+pub const SQL_CREATE_EMAIL_CHANGE: &str = include_str!("../../../database/queries/email_changes/create.sql");
+pub const SQL_CONSUME_EMAIL_CHANGE: &str = include_str!("../../../database/queries/email_changes/consume.sql");
+
+// Outbox
+pub const SQL_CREATE_OUTBOX_MESSAGE: &str = include_str!("../../../database/queries/outbox/create.sql");
+
+// Registration invites
+pub const SQL_CREATE_INVITE: &str = include_str!("../../../database/queries/invites/create.sql");
+pub const SQL_CONSUME_INVITE: &str = include_str!("../../../database/queries/invites/consume.sql");
+
+// Roles
+pub const SQL_LIST_ROLES: &str = include_str!("../../../database/queries/roles/list.sql");
+pub const SQL_CLEAR_USER_ROLES: &str = include_str!("../../../database/queries/roles/clear_user.sql");
+pub const SQL_REMOVE_USER_ROLE: &str = include_str!("../../../database/queries/roles/remove_user_role.sql");
+pub const SQL_ASSIGN_USER_ROLES: &str = include_str!("../../../database/queries/roles/assign_user.sql");
+
+pub const SQL_GET_USER_PERMISSIONS: &str = include_str!("../../../database/queries/roles/user_permissions.sql");
+
+// OIDC
+pub const SQL_CREATE_OIDC_STATE: &str = include_str!("../../../database/queries/oidc/create_state.sql");
+pub const SQL_CONSUME_OIDC_STATE: &str = include_str!("../../../database/queries/oidc/consume_state.sql");
+pub const SQL_GET_OIDC_IDENTITY: &str = include_str!("../../../database/queries/oidc/get_identity.sql");
+pub const SQL_LINK_OIDC_IDENTITY: &str = include_str!("../../../database/queries/oidc/link_identity.sql");
+
+// Users. Lookups use the `*_active` variants, which skip soft-deleted users; the other
+// implementations sharing the database still use `get.sql`, `list.sql`...
 pub const SQL_CREATE_USER: &str = include_str!("../../../database/queries/users/create.sql");
-pub const SQL_GET_USER: &str = include_str!("../../../database/queries/users/get.sql");
-pub const SQL_LIST_USERS: &str = include_str!("../../../database/queries/users/list.sql");
-pub const SQL_UPDATE_USER: &str = include_str!("../../../database/queries/users/update.sql");
-pub const SQL_DELETE_USER: &str = include_str!("../../../database/queries/users/delete.sql");
+pub const SQL_CREATE_USERS_BATCH: &str = include_str!("../../../database/queries/users/create_batch.sql");
+pub const SQL_GET_USER: &str = include_str!("../../../database/queries/users/get_active.sql");
+pub const SQL_GET_USER_INCLUDING_DELETED: &str = include_str!("../../../database/queries/users/get.sql");
+pub const SQL_IS_USER_ACTIVE: &str = include_str!("../../../database/queries/users/is_active.sql");
+pub const SQL_GET_USER_BY_EMAIL: &str = include_str!("../../../database/queries/users/get_active_by_email.sql");
+pub const SQL_LIST_USERS: &str = include_str!("../../../database/queries/users/list_active.sql");
+pub const SQL_SEARCH_USERS: &str = include_str!("../../../database/queries/users/search_active.sql");
+pub const SQL_GET_USER_PROFILE: &str = include_str!("../../../database/queries/users/profile.sql");
+pub const SQL_GET_USER_PROFILE_BY_USERNAME: &str = include_str!("../../../database/queries/users/profile_by_username.sql");
+pub const SQL_UPDATE_USER: &str = include_str!("../../../database/queries/users/update_active.sql");
+pub const SQL_UPDATE_USER_EMAIL: &str = include_str!("../../../database/queries/users/update_email.sql");
+pub const SQL_UPDATE_USER_USERNAME: &str = include_str!("../../../database/queries/users/update_username.sql");
+pub const SQL_UPDATE_USER_BIO: &str = include_str!("../../../database/queries/users/update_bio.sql");
+pub const SQL_UPDATE_USER_PASSWORD: &str = include_str!("../../../database/queries/users/update_password.sql");
+pub const SQL_REHASH_USER_PASSWORD: &str = include_str!("../../../database/queries/users/rehash_password.sql");
+pub const SQL_SOFT_DELETE_USER: &str = include_str!("../../../database/queries/users/soft_delete.sql");
+pub const SQL_RESTORE_USER: &str = include_str!("../../../database/queries/users/restore.sql");
+pub const SQL_PURGE_DELETED_USERS: &str = include_str!("../../../database/queries/users/purge.sql");
 
 // Posts
 pub const SQL_CREATE_POST: &str = include_str!("../../../database/queries/posts/create.sql");
 pub const SQL_LIST_POSTS: &str = include_str!("../../../database/queries/posts/list.sql");
 pub const SQL_GET_POST: &str = include_str!("../../../database/queries/posts/get.sql");
 pub const SQL_GET_POST_AUTHOR: &str = include_str!("../../../database/queries/posts/get_author.sql");
+pub const SQL_LIST_POSTS_BY_AUTHOR: &str = include_str!("../../../database/queries/posts/list_by_author.sql");
 pub const SQL_DELETE_POST: &str = include_str!("../../../database/queries/posts/delete.sql");
 
 // Comments
 pub const SQL_CREATE_COMMENT: &str = include_str!("../../../database/queries/comments/create.sql");
 pub const SQL_LIST_COMMENTS: &str = include_str!("../../../database/queries/comments/list.sql");
+pub const SQL_LIST_COMMENTS_BY_AUTHOR: &str = include_str!("../../../database/queries/comments/list_by_author.sql");
 
 // Likes
 pub const SQL_CREATE_LIKE: &str = include_str!("../../../database/queries/likes/create.sql");
 pub const SQL_DELETE_LIKE: &str = include_str!("../../../database/queries/likes/delete.sql");
+pub const SQL_LIST_LIKES_BY_USER: &str = include_str!("../../../database/queries/likes/list_by_user.sql");
+
+// Follows
+pub const SQL_CREATE_FOLLOW: &str = include_str!("../../../database/queries/follows/create.sql");
+pub const SQL_DELETE_FOLLOW: &str = include_str!("../../../database/queries/follows/delete.sql");
+pub const SQL_LIST_FOLLOWERS: &str = include_str!("../../../database/queries/follows/list_followers.sql");
+pub const SQL_LIST_FOLLOWING: &str = include_str!("../../../database/queries/follows/list_following.sql");
+
+// Audit log
+pub const SQL_CREATE_AUDIT_ENTRY: &str = include_str!("../../../database/queries/audit/create.sql");